@@ -0,0 +1,44 @@
+// Compares the RTree and the hashed uniform grid on full aggregation runs.
+//
+// cargo bench --bench spatial_index -- [particles]
+use dla::{Builder, SpatialIndex, UniformGrid2d, UniformGrid3d, Vec2d, Vec3d};
+
+use std::time::Instant;
+
+const DEFAULT_PARTICLES: usize = 1_000_000;
+
+fn flat<I: SpatialIndex<Vec2d>>(name: &str, index: I, particles: usize) {
+    let mut model = Builder::flat_with_index(index);
+    model.add_seed([0., 0.].into());
+
+    let now = Instant::now();
+    for _ in 0..particles {
+        model.add_particle();
+    }
+    println!("2d {:>6}: {:>8} ms", name, now.elapsed().as_millis());
+}
+
+fn convex<I: SpatialIndex<Vec3d>>(name: &str, index: I, particles: usize) {
+    let mut model = Builder::convex_with_index(index);
+    model.add_seed([0., 0., 0.].into());
+
+    let now = Instant::now();
+    for _ in 0..particles {
+        model.add_particle();
+    }
+    println!("3d {:>6}: {:>8} ms", name, now.elapsed().as_millis());
+}
+
+fn main() {
+    let particles = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_PARTICLES);
+
+    println!("{} particles", particles);
+
+    flat("rtree", rstar::RTree::new(), particles);
+    flat("grid", UniformGrid2d::default(), particles);
+    convex("rtree", rstar::RTree::new(), particles);
+    convex("grid", UniformGrid3d::default(), particles);
+}
@@ -3,7 +3,7 @@ use dla::{Builder, Vec3d};
 fn main() {
     let mut model = Builder::convex();
 
-    model.add([0., 0., 0.].into(), 1);
+    model.add_seed([0., 0., 0.].into());
 
     for _ in 0..100_000 {
         model.add_particle();
@@ -23,7 +23,7 @@ fn palette(d: f64) -> [f64; 3] {
     let darkblack = Vec3d::new(0.05, 0.19, 0.3);
     let black = Vec3d::new(0., 0., 0.01);
 
-    let x = d.max(0.).min(1.);
+    let x = d.clamp(0., 1.);
     if x < 0.25 {
         return Vec3d::lerp(black, darkblack, x * 4.).as_slice();
     } else if x < 0.5 {
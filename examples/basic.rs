@@ -3,7 +3,7 @@ use dla::Builder;
 fn main() {
     let mut model = Builder::flat();
 
-    model.add([0., 0.].into(), 1);
+    model.add_seed([0., 0.].into());
 
     for _ in 0..10000 {
         model.add_particle();
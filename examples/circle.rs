@@ -8,7 +8,7 @@ fn main() {
         let r = 50.;
         let x = r * angle.cos();
         let y = r * angle.sin();
-        model.add([x, y].into(), i);
+        model.add_seed([x, y].into());
     }
 
     for _ in 0..1000 {
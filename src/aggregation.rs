@@ -0,0 +1,75 @@
+use crate::error::Result;
+use crate::structures::Vec3d;
+
+// Aggregation is what FlatAggregation, ConvexAggregation,
+// CompactAggregation, SurfaceAggregation, FlatColonization and
+// ConvexColonization have in common, so code that reads or grows
+// aggregates can take any of them.
+//
+// Vector is the type particles are added as, Vec2d for flat aggregates and
+// Vec3d for the others. Positions are handed out as Vec3d either way, flat
+// ones with a z of 0.
+//
+// Particles are numbered from 0 in the order they were added, seeds are
+// their own parent.
+pub trait Aggregation {
+    type Vector;
+
+    /// Adds the particle `p`, joined to `parent`, or as a seed when `parent`
+    /// isn't an already added particle.
+    fn add(&mut self, p: Self::Vector, parent: usize);
+
+    /// Adds the particle `p` as a seed of its own. Several seeds have to be
+    /// added this way, `add(p, 0)` joins `p` to particle 0 once there is one.
+    fn add_seed(&mut self, p: Self::Vector) {
+        let id = self.len();
+        self.add(p, id);
+    }
+
+    /// Grows the aggregate by a single particle, panics when there is
+    /// nothing to grow from. Space colonization grows by a whole step.
+    fn add_particle(&mut self);
+
+    /// Like `add_particle`, but returns the reason instead of panicking.
+    fn try_add_particle(&mut self) -> Result<()>;
+
+    /// Number of particles.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 2 for flat aggregates, 3 for the others.
+    fn dimensions(&self) -> usize;
+
+    fn position(&self, index: usize) -> Vec3d;
+
+    fn parent(&self, index: usize) -> usize;
+
+    /// Every particle within `radius` of `p`, in no particular order, from
+    /// the aggregate's own spatial index. Flat aggregates ignore the z of
+    /// `p`.
+    fn within(&self, p: Vec3d, radius: f64) -> Vec<usize>;
+
+    /// Every position, in the order particles were added.
+    fn positions(&self) -> Vec<Vec3d> {
+        (0..self.len()).map(|i| self.position(i)).collect()
+    }
+
+    /// Every parent, in the order particles were added.
+    fn parents(&self) -> Vec<usize> {
+        (0..self.len()).map(|i| self.parent(i)).collect()
+    }
+
+    fn particle_spacing(&self) -> f64;
+
+    /// The distance walkers are caught from, which the analyses take as the
+    /// smallest scale worth measuring at. Space colonization has no walkers
+    /// and returns its kill distance instead, the closest an attractor gets
+    /// to a node before it is reached.
+    fn attraction_distance(&self) -> f64;
+
+    /// Radius of a sphere around the origin that holds every particle.
+    fn bounding_radius(&self) -> f64;
+}
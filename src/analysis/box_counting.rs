@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+
+use crate::aggregation::Aggregation;
+use crate::analysis::{log_steps, Fit};
+
+// BoxCounting settings for estimating the box-counting dimension of an
+// aggregate. Its bounding box is covered with boxes of a range of sizes,
+// and the number of boxes holding at least one particle is fitted against
+// their size on a log-log scale.
+//
+// Min_size and max_size bound the box sizes. By default they are four
+// times attraction_distance, below which branches look like lines rather
+// than a fractal, and a quarter of the largest side of the bounding box,
+// above which a handful of boxes covers everything.
+//
+// Steps is the number of box sizes, spread evenly on a log scale.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoxCounting {
+    min_size: Option<f64>,
+    max_size: Option<f64>,
+    steps: usize,
+}
+
+// BoxDimension is the outcome of BoxCounting::estimate.
+//
+// Dimension is the estimated dimension and interval its 95% confidence
+// interval, from fit, where the slope is minus the dimension.
+//
+// Counts holds the box sizes and how many boxes of each were occupied, for
+// plotting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoxDimension {
+    pub dimension: f64,
+    pub interval: (f64, f64),
+    pub fit: Fit,
+    pub counts: Vec<(f64, usize)>,
+}
+
+impl Default for BoxCounting {
+    fn default() -> BoxCounting {
+        BoxCounting {
+            min_size: None,
+            max_size: None,
+            steps: 10,
+        }
+    }
+}
+
+impl BoxCounting {
+    pub fn new() -> BoxCounting {
+        BoxCounting::default()
+    }
+
+    pub fn with_min_size(mut self, a: f64) -> BoxCounting {
+        self.min_size = Some(a);
+        self
+    }
+
+    pub fn with_max_size(mut self, a: f64) -> BoxCounting {
+        self.max_size = Some(a);
+        self
+    }
+
+    pub fn with_steps(mut self, a: usize) -> BoxCounting {
+        self.steps = a;
+        self
+    }
+
+    pub fn estimate<A: Aggregation>(&self, model: &A) -> BoxDimension {
+        let dimensions = model.dimensions();
+        let points: Vec<[f64; 3]> = model.positions().iter().map(|p| p.as_slice()).collect();
+
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for p in &points {
+            for axis in 0..dimensions {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+        let extent = (0..dimensions)
+            .map(|axis| max[axis] - min[axis])
+            .fold(0., f64::max);
+
+        let smallest = self
+            .min_size
+            .unwrap_or_else(|| 4. * model.attraction_distance());
+        let largest = self.max_size.unwrap_or(extent / 4.);
+
+        let mut counts = Vec::new();
+        for size in log_steps(smallest, largest, self.steps) {
+            let mut boxes = HashSet::new();
+            for p in &points {
+                let mut cell = [0i64; 3];
+                for axis in 0..dimensions {
+                    cell[axis] = ((p[axis] - min[axis]) / size).floor() as i64;
+                }
+                boxes.insert(cell);
+            }
+            counts.push((size, boxes.len()));
+        }
+
+        let samples: Vec<_> = counts.iter().map(|&(s, n)| (s, n as f64)).collect();
+        let fit = Fit::log_log(&samples);
+        BoxDimension {
+            dimension: -fit.slope,
+            interval: (-fit.interval.1, -fit.interval.0),
+            fit,
+            counts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FlatAggregation;
+
+    #[test]
+    fn filled_square_is_two_dimensional() {
+        let mut model = FlatAggregation::new();
+        for x in 0..200 {
+            for y in 0..200 {
+                model.add([x as f64, y as f64].into(), 0);
+            }
+        }
+        // well below the side, the few boxes of the default largest sizes
+        // mostly stick out past the edges
+        let estimate = BoxCounting::new()
+            .with_min_size(4.)
+            .with_max_size(25.)
+            .estimate(&model);
+        assert!(
+            (estimate.dimension - 2.).abs() < 0.05,
+            "{:?}",
+            estimate.counts
+        );
+        assert!(
+            estimate.interval.0 <= estimate.dimension && estimate.dimension <= estimate.interval.1
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::f64::consts::PI;
+
+use crate::aggregation::Aggregation;
+use crate::analysis::{log_steps, Fit};
+
+// PairCorrelation settings for the density correlation function C(r) of an
+// aggregate, the mean density of particles at distance r from a particle.
+// Pairs closer than cutoff are found with the aggregate's own spatial
+// index, see Aggregation::within, and binned by distance, and C(r) falls
+// off as r to the power of D - d, where D is the dimension of the
+// aggregate and d the one of the space it grew in.
+//
+// Min_radius and cutoff bound the distances, one and sixteen times
+// attraction_distance by default. Every particle has about cutoff to the
+// power of D others within the cutoff, which is what the time taken grows
+// with.
+//
+// Bins is the number of bins between them, spread evenly on a log scale.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PairCorrelation {
+    min_radius: Option<f64>,
+    cutoff: Option<f64>,
+    bins: usize,
+}
+
+// Correlation is the outcome of PairCorrelation::estimate.
+//
+// Exponent is the slope of fit, D - d, and dimension D itself with interval
+// its 95% confidence interval.
+//
+// Bins holds the middle of every bin, on a log scale, and C(r) there, for
+// plotting. Empty bins are left out of the fit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Correlation {
+    pub exponent: f64,
+    pub dimension: f64,
+    pub interval: (f64, f64),
+    pub fit: Fit,
+    pub bins: Vec<(f64, f64)>,
+}
+
+impl Default for PairCorrelation {
+    fn default() -> PairCorrelation {
+        PairCorrelation {
+            min_radius: None,
+            cutoff: None,
+            bins: 20,
+        }
+    }
+}
+
+impl PairCorrelation {
+    pub fn new() -> PairCorrelation {
+        PairCorrelation::default()
+    }
+
+    pub fn with_min_radius(mut self, a: f64) -> PairCorrelation {
+        self.min_radius = Some(a);
+        self
+    }
+
+    pub fn with_cutoff(mut self, a: f64) -> PairCorrelation {
+        self.cutoff = Some(a);
+        self
+    }
+
+    pub fn with_bins(mut self, a: usize) -> PairCorrelation {
+        self.bins = a;
+        self
+    }
+
+    pub fn estimate<A: Aggregation>(&self, model: &A) -> Correlation {
+        let smallest = self
+            .min_radius
+            .unwrap_or_else(|| model.attraction_distance());
+        let cutoff = self
+            .cutoff
+            .unwrap_or_else(|| 16. * model.attraction_distance());
+        let edges: Vec<f64> = log_steps(smallest, cutoff, self.bins + 1).collect();
+
+        let points = model.positions();
+        let mut pairs = vec![0usize; edges.len().saturating_sub(1)];
+        for (i, &p) in points.iter().enumerate() {
+            for j in model.within(p, cutoff) {
+                let r = p.distance(points[j]);
+                if j == i || r < smallest {
+                    continue;
+                }
+                // the first edge past r closes its bin
+                let bin = edges.partition_point(|&e| e <= r);
+                if bin > 0 && bin < edges.len() {
+                    pairs[bin - 1] += 1;
+                }
+            }
+        }
+
+        let d = model.dimensions();
+        let shell = |r: f64| match d {
+            2 => PI * r * r,
+            _ => 4. / 3. * PI * r * r * r,
+        };
+        let n = points.len().max(1) as f64;
+        let bins: Vec<(f64, f64)> = pairs
+            .iter()
+            .zip(edges.windows(2))
+            .map(|(&count, e)| {
+                let volume = shell(e[1]) - shell(e[0]);
+                ((e[0] * e[1]).sqrt(), count as f64 / n / volume)
+            })
+            .collect();
+
+        let fit = Fit::log_log(&bins);
+        let d = d as f64;
+        Correlation {
+            exponent: fit.slope,
+            dimension: fit.slope + d,
+            interval: (fit.interval.0 + d, fit.interval.1 + d),
+            fit,
+            bins,
+        }
+    }
+}
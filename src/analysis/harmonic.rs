@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+
+use crate::aggregation::Aggregation;
+use crate::analysis::{log_steps, Fit};
+use crate::structures::Vec3d;
+
+// HarmonicMeasure is the outcome of probing an aggregate with test
+// walkers, see ConvexAggregation::probe and FlatAggregation::probe. They
+// move and stick by the rules of the aggregate, but are counted rather than
+// added, so it is left as it was. CompactAggregation and
+// SurfaceAggregation can't be probed.
+//
+// Walkers is the number of test walkers launched and hits holds how many of
+// them stuck to every particle. Missed is the number that stuck to a mesh
+// instead.
+//
+// Probabilities holds the share of the stuck walkers that stuck to every
+// particle, the chance that the next particle grows on it. Missed walkers
+// are left out, so the shares add up to 1. It is highest at the tips that are still growing, and close to zero
+// deep inside the fjords.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HarmonicMeasure {
+    pub walkers: usize,
+    pub hits: Vec<usize>,
+    pub missed: usize,
+    pub probabilities: Vec<f64>,
+    points: Vec<Vec3d>,
+    dimensions: usize,
+    attraction_distance: f64,
+}
+
+// Multifractal settings for the f(alpha) spectrum of a harmonic measure,
+// found with the method of Chhabra and Jensen. The particles are covered
+// with boxes of a range of sizes, the probabilities within every box are
+// summed, and for every moment q the boxes are weighted by their
+// probability to the power of q. The weighted mean of the log of the
+// probabilities gives alpha and the entropy of the weights gives f, each as
+// the slope of a fit against the log of the box size.
+//
+// Moments holds the values of q, 0 to 8 by default. Negative moments pick
+// out the least visited boxes, which a finite number of walkers samples
+// poorly.
+//
+// Min_size, max_size and steps are the box sizes as in BoxCounting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Multifractal {
+    moments: Vec<f64>,
+    min_size: Option<f64>,
+    max_size: Option<f64>,
+    steps: usize,
+}
+
+// Spectrum is the outcome of Multifractal::estimate, a singularity for every
+// moment. Sizes holds the box sizes the fits were made over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrum {
+    pub singularities: Vec<Singularity>,
+    pub sizes: Vec<f64>,
+}
+
+// Singularity is a point on the f(alpha) spectrum. Alpha is the strength of
+// the singularity of the boxes that dominate moment q and f the dimension
+// of the set they make up.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Singularity {
+    pub q: f64,
+    pub alpha: f64,
+    pub f: f64,
+}
+
+impl HarmonicMeasure {
+    pub(crate) fn new<A: Aggregation>(
+        model: &A,
+        hits: Vec<usize>,
+        missed: usize,
+    ) -> HarmonicMeasure {
+        let stuck: usize = hits.iter().sum();
+        let probabilities = hits
+            .iter()
+            .map(|&h| {
+                if stuck > 0 {
+                    h as f64 / stuck as f64
+                } else {
+                    0.
+                }
+            })
+            .collect();
+        HarmonicMeasure {
+            walkers: stuck + missed,
+            hits,
+            missed,
+            probabilities,
+            points: model.positions(),
+            dimensions: model.dimensions(),
+            attraction_distance: model.attraction_distance(),
+        }
+    }
+
+    // Probabilities on a log scale from 0 for the least hit particles to 1
+    // for the most hit, for colouring renders. Particles that were never hit
+    // are 0 too.
+    pub fn shades(&self) -> Vec<f64> {
+        let logs: Vec<f64> = self
+            .probabilities
+            .iter()
+            .filter(|&&p| p > 0.)
+            .map(|p| p.ln())
+            .collect();
+        let lowest = logs.iter().copied().fold(f64::INFINITY, f64::min);
+        let highest = logs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        self.probabilities
+            .iter()
+            .map(|&p| {
+                if p > 0. && highest > lowest {
+                    (p.ln() - lowest) / (highest - lowest)
+                } else if p > 0. {
+                    1.
+                } else {
+                    0.
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for Multifractal {
+    fn default() -> Multifractal {
+        Multifractal {
+            moments: (0..=16).map(|q| q as f64 / 2.).collect(),
+            min_size: None,
+            max_size: None,
+            steps: 10,
+        }
+    }
+}
+
+impl Multifractal {
+    pub fn new() -> Multifractal {
+        Multifractal::default()
+    }
+
+    pub fn with_moments(mut self, a: &[f64]) -> Multifractal {
+        self.moments = a.to_vec();
+        self
+    }
+
+    pub fn with_min_size(mut self, a: f64) -> Multifractal {
+        self.min_size = Some(a);
+        self
+    }
+
+    pub fn with_max_size(mut self, a: f64) -> Multifractal {
+        self.max_size = Some(a);
+        self
+    }
+
+    pub fn with_steps(mut self, a: usize) -> Multifractal {
+        self.steps = a;
+        self
+    }
+
+    pub fn estimate(&self, measure: &HarmonicMeasure) -> Spectrum {
+        let dimensions = measure.dimensions;
+        let points: Vec<[f64; 3]> = measure.points.iter().map(|p| p.as_slice()).collect();
+
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for p in &points {
+            for axis in 0..dimensions {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+        let extent = (0..dimensions)
+            .map(|axis| max[axis] - min[axis])
+            .fold(0., f64::max);
+
+        let smallest = self.min_size.unwrap_or(4. * measure.attraction_distance);
+        let largest = self.max_size.unwrap_or(extent / 4.);
+        let sizes: Vec<f64> = log_steps(smallest, largest, self.steps).collect();
+
+        // the summed probability of every box that was hit, for every size
+        let boxes: Vec<Vec<f64>> = sizes
+            .iter()
+            .map(|&size| {
+                let mut boxes = HashMap::new();
+                for (p, &probability) in points.iter().zip(&measure.probabilities) {
+                    if probability <= 0. {
+                        continue;
+                    }
+                    let mut cell = [0i64; 3];
+                    for axis in 0..dimensions {
+                        cell[axis] = ((p[axis] - min[axis]) / size).floor() as i64;
+                    }
+                    *boxes.entry(cell).or_insert(0.) += probability;
+                }
+                boxes.into_values().collect()
+            })
+            .collect();
+
+        let singularities = self
+            .moments
+            .iter()
+            .map(|&q| {
+                let mut alphas = Vec::with_capacity(sizes.len());
+                let mut fs = Vec::with_capacity(sizes.len());
+                for (&size, boxes) in sizes.iter().zip(&boxes) {
+                    let total: f64 = boxes.iter().map(|p| p.powf(q)).sum();
+                    let (mut alpha, mut f) = (0., 0.);
+                    for &p in boxes {
+                        let weight = p.powf(q) / total;
+                        alpha += weight * p.ln();
+                        f += weight * weight.ln();
+                    }
+                    alphas.push((size.ln(), alpha));
+                    fs.push((size.ln(), f));
+                }
+                Singularity {
+                    q,
+                    alpha: Fit::linear(&alphas).slope,
+                    f: Fit::linear(&fs).slope,
+                }
+            })
+            .collect();
+
+        Spectrum {
+            singularities,
+            sizes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FlatAggregation;
+
+    // Every box of a filled square holds the same share of an even measure,
+    // so its spectrum collapses to the single point alpha = f = 2
+    #[test]
+    fn even_measure_on_a_square_is_monofractal() {
+        let mut model = FlatAggregation::new();
+        for x in 0..64 {
+            for y in 0..64 {
+                model.add([x as f64, y as f64].into(), 0);
+            }
+        }
+        let measure = HarmonicMeasure::new(&model, vec![1; model.len()], 0);
+        assert_eq!(measure.walkers, 64 * 64);
+
+        let spectrum = Multifractal::new()
+            .with_moments(&[0., 1., 2., 5.])
+            .with_min_size(2.)
+            .with_max_size(16.)
+            .with_steps(4)
+            .estimate(&measure);
+        assert_eq!(spectrum.sizes.len(), 4);
+        for s in &spectrum.singularities {
+            assert!((s.alpha - 2.).abs() < 1e-6, "{:?}", s);
+            assert!((s.f - 2.).abs() < 1e-6, "{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::aggregation::Aggregation;
+use crate::analysis::{log_steps, Fit};
+use crate::structures::Vec3d;
+
+// MassRadius settings for estimating the mass-radius dimension of an
+// aggregate. The particles within a range of distances from the center are
+// counted, and the count is fitted against the distance on a log-log
+// scale.
+//
+// Center is the first particle by default, the seed of single seeded
+// aggregates.
+//
+// Min_radius and max_radius bound the distances. By default they are four
+// times attraction_distance and half the distance to the furthest
+// particle, beyond which the aggregate is still being screened off and
+// thins out.
+//
+// Steps is the number of distances, spread evenly on a log scale.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MassRadius {
+    center: Option<Vec3d>,
+    min_radius: Option<f64>,
+    max_radius: Option<f64>,
+    steps: usize,
+}
+
+// MassDimension is the outcome of MassRadius::estimate.
+//
+// Dimension is the slope of fit and interval its 95% confidence interval.
+//
+// Masses holds the distances and how many particles lie within each of
+// them, for plotting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MassDimension {
+    pub dimension: f64,
+    pub interval: (f64, f64),
+    pub fit: Fit,
+    pub masses: Vec<(f64, usize)>,
+}
+
+// GyrationHistory settings for following the radius of gyration while the
+// aggregate grew. Particles are numbered in the order they were added, so
+// the first n of them are the aggregate as it was after n particles, and
+// the radius of gyration of those is found for a range of n. Its growth as
+// n to the power of 1 / D gives another estimate of the dimension D.
+//
+// Min_particles is the smallest n looked at, and the one the fit starts
+// from. Small aggregates are mostly seed and haven't settled into their
+// scaling yet.
+//
+// Steps is the number of values of n, spread evenly on a log scale up to
+// every particle.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GyrationHistory {
+    min_particles: usize,
+    steps: usize,
+}
+
+// GyrationDimension is the outcome of GyrationHistory::estimate.
+//
+// Dimension and interval are the estimated dimension and its 95%
+// confidence interval, fit the fit of the radius of gyration against n on
+// a log-log scale, whose slope is one over the dimension.
+//
+// Radii holds each n and the radius of gyration of the first n particles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GyrationDimension {
+    pub dimension: f64,
+    pub interval: (f64, f64),
+    pub fit: Fit,
+    pub radii: Vec<(usize, f64)>,
+}
+
+impl Default for MassRadius {
+    fn default() -> MassRadius {
+        MassRadius {
+            center: None,
+            min_radius: None,
+            max_radius: None,
+            steps: 10,
+        }
+    }
+}
+
+impl MassRadius {
+    pub fn new() -> MassRadius {
+        MassRadius::default()
+    }
+
+    pub fn with_center(mut self, a: Vec3d) -> MassRadius {
+        self.center = Some(a);
+        self
+    }
+
+    pub fn with_min_radius(mut self, a: f64) -> MassRadius {
+        self.min_radius = Some(a);
+        self
+    }
+
+    pub fn with_max_radius(mut self, a: f64) -> MassRadius {
+        self.max_radius = Some(a);
+        self
+    }
+
+    pub fn with_steps(mut self, a: usize) -> MassRadius {
+        self.steps = a;
+        self
+    }
+
+    pub fn estimate<A: Aggregation>(&self, model: &A) -> MassDimension {
+        let center = match self.center {
+            Some(c) => c,
+            None if model.is_empty() => Vec3d::zero(),
+            None => model.position(0),
+        };
+        let mut distances: Vec<f64> = model
+            .positions()
+            .iter()
+            .map(|p| p.distance(center))
+            .collect();
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let furthest = distances.last().copied().unwrap_or(0.);
+        let smallest = self
+            .min_radius
+            .unwrap_or_else(|| 4. * model.attraction_distance());
+        let largest = self.max_radius.unwrap_or(furthest / 2.);
+
+        let masses: Vec<_> = log_steps(smallest, largest, self.steps)
+            .map(|r| (r, distances.partition_point(|&d| d <= r)))
+            .collect();
+
+        let samples: Vec<_> = masses.iter().map(|&(r, m)| (r, m as f64)).collect();
+        let fit = Fit::log_log(&samples);
+        MassDimension {
+            dimension: fit.slope,
+            interval: fit.interval,
+            fit,
+            masses,
+        }
+    }
+}
+
+impl Default for GyrationHistory {
+    fn default() -> GyrationHistory {
+        GyrationHistory {
+            min_particles: 100,
+            steps: 20,
+        }
+    }
+}
+
+impl GyrationHistory {
+    pub fn new() -> GyrationHistory {
+        GyrationHistory::default()
+    }
+
+    pub fn with_min_particles(mut self, a: usize) -> GyrationHistory {
+        self.min_particles = a;
+        self
+    }
+
+    pub fn with_steps(mut self, a: usize) -> GyrationHistory {
+        self.steps = a;
+        self
+    }
+
+    pub fn estimate<A: Aggregation>(&self, model: &A) -> GyrationDimension {
+        let len = model.len();
+        let mut counts: Vec<usize> =
+            log_steps(self.min_particles.max(1) as f64, len as f64, self.steps)
+                .map(|n| n.round() as usize)
+                .collect();
+        counts.dedup();
+
+        // running sums of positions and their squares over the first n
+        let mut radii = Vec::with_capacity(counts.len());
+        let mut sum = Vec3d::zero();
+        let mut squares = 0.;
+        let mut next = counts.iter().peekable();
+        for i in 0..len {
+            let p = model.position(i);
+            sum += p;
+            squares += p.length_squared();
+            let n = i + 1;
+            if next.peek() == Some(&&n) {
+                let center = sum / n as f64;
+                let r = (squares / n as f64 - center.length_squared())
+                    .max(0.)
+                    .sqrt();
+                radii.push((n, r));
+                next.next();
+            }
+        }
+
+        let samples: Vec<_> = radii.iter().map(|&(n, r)| (n as f64, r)).collect();
+        let fit = Fit::log_log(&samples);
+        GyrationDimension {
+            dimension: 1. / fit.slope,
+            interval: (1. / fit.interval.1, 1. / fit.interval.0),
+            fit,
+            radii,
+        }
+    }
+}
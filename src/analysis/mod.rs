@@ -0,0 +1,162 @@
+// Measures of grown aggregates, from the positions and parents any
+// Aggregation hands out
+pub mod box_counting;
+pub mod correlation;
+pub mod harmonic;
+pub mod mass_radius;
+pub mod shape;
+pub mod topology;
+
+pub use box_counting::{BoxCounting, BoxDimension};
+pub use correlation::{Correlation, PairCorrelation};
+pub use harmonic::{HarmonicMeasure, Multifractal, Singularity, Spectrum};
+pub use mass_radius::{GyrationDimension, GyrationHistory, MassDimension, MassRadius};
+pub use shape::Shape;
+pub use topology::{Branch, Topology};
+
+// Fit is a straight line y = intercept + slope * x fitted by least
+// squares, with the 95% confidence interval of the slope.
+//
+// Slope_error is the standard error of the slope. With fewer than three
+// samples there is nothing to estimate it from and it is NaN, as are the
+// bounds of the interval.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fit {
+    pub slope: f64,
+    pub intercept: f64,
+    pub slope_error: f64,
+    pub interval: (f64, f64),
+    pub samples: usize,
+}
+
+impl Fit {
+    pub fn linear(samples: &[(f64, f64)]) -> Fit {
+        let n = samples.len() as f64;
+        let mean_x = samples.iter().map(|s| s.0).sum::<f64>() / n;
+        let mean_y = samples.iter().map(|s| s.1).sum::<f64>() / n;
+        let (mut sxx, mut sxy) = (0., 0.);
+        for (x, y) in samples {
+            sxx += (x - mean_x) * (x - mean_x);
+            sxy += (x - mean_x) * (y - mean_y);
+        }
+        let slope = sxy / sxx;
+        let intercept = mean_y - slope * mean_x;
+
+        let slope_error = if samples.len() > 2 {
+            let residuals: f64 = samples
+                .iter()
+                .map(|(x, y)| (y - intercept - slope * x).powi(2))
+                .sum();
+            (residuals / (n - 2.) / sxx).sqrt()
+        } else {
+            f64::NAN
+        };
+        let t = student_t(samples.len().saturating_sub(2));
+
+        Fit {
+            slope,
+            intercept,
+            slope_error,
+            interval: (slope - t * slope_error, slope + t * slope_error),
+            samples: samples.len(),
+        }
+    }
+
+    // Fits log y against log x, the slope is the exponent of a power law.
+    // Samples that aren't positive have no logarithm and are left out.
+    pub fn log_log(samples: &[(f64, f64)]) -> Fit {
+        let logs: Vec<_> = samples
+            .iter()
+            .filter(|(x, y)| *x > 0. && *y > 0.)
+            .map(|(x, y)| (x.ln(), y.ln()))
+            .collect();
+        Fit::linear(&logs)
+    }
+}
+
+// Two sided 97.5% quantile of Student's t distribution with degrees of
+// freedom
+fn student_t(degrees: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    match degrees {
+        0 => f64::NAN,
+        d if d <= TABLE.len() => TABLE[d - 1],
+        _ => 1.96,
+    }
+}
+
+// Steps values from smallest to largest, spread evenly on a log scale, or
+// none when largest isn't past smallest
+pub(crate) fn log_steps(smallest: f64, largest: f64, steps: usize) -> impl Iterator<Item = f64> {
+    let steps = if smallest > 0. && largest > smallest {
+        steps
+    } else {
+        0
+    };
+    (0..steps).map(move |step| {
+        let t = if steps > 1 {
+            step as f64 / (steps - 1) as f64
+        } else {
+            0.
+        };
+        smallest * (largest / smallest).powf(t)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn linear_fit_of_an_exact_line() {
+        let samples: Vec<_> = (0..5).map(|x| (x as f64, 1. + 2. * x as f64)).collect();
+        let fit = Fit::linear(&samples);
+        assert!(close(fit.slope, 2.) && close(fit.intercept, 1.));
+        assert!(close(fit.slope_error, 0.));
+        assert!(close(fit.interval.0, 2.) && close(fit.interval.1, 2.));
+        assert_eq!(fit.samples, 5);
+    }
+
+    #[test]
+    fn linear_fit_of_scattered_samples() {
+        let fit = Fit::linear(&[(0., 0.), (1., 1.), (2., 1.), (3., 2.)]);
+        assert!(close(fit.slope, 0.6) && close(fit.intercept, 0.1));
+        // residuals of 0.1, 0.3, 0.3 and 0.1 over 2 degrees of freedom
+        assert!(close(fit.slope_error, 0.02f64.sqrt()));
+        assert!(close(fit.interval.1 - fit.slope, 4.303 * 0.02f64.sqrt()));
+    }
+
+    #[test]
+    fn linear_fit_of_two_samples_has_no_interval() {
+        let fit = Fit::linear(&[(0., 0.), (1., 3.)]);
+        assert!(close(fit.slope, 3.));
+        assert!(fit.slope_error.is_nan() && fit.interval.0.is_nan());
+    }
+
+    #[test]
+    fn student_t_quantiles() {
+        assert!(student_t(0).is_nan());
+        assert_eq!(student_t(1), 12.706);
+        assert_eq!(student_t(10), 2.228);
+        assert_eq!(student_t(30), 2.042);
+        assert_eq!(student_t(1000), 1.96);
+    }
+
+    #[test]
+    fn log_steps_spread_evenly_on_a_log_scale() {
+        let steps: Vec<_> = log_steps(1., 100., 3).collect();
+        assert_eq!(steps.len(), 3);
+        assert!(close(steps[0], 1.) && close(steps[1], 10.) && close(steps[2], 100.));
+        assert_eq!(log_steps(2., 8., 1).collect::<Vec<_>>(), vec![2.]);
+        assert_eq!(log_steps(5., 1., 3).count(), 0);
+        assert_eq!(log_steps(0., 1., 3).count(), 0);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::aggregation::Aggregation;
+use crate::structures::Vec3d;
+use crate::symmetry::{apply3, Matrix3};
+
+// Shape describes the overall shape of an aggregate through its gyration
+// tensor, the mean outer product of the particle positions around their
+// center.
+//
+// Tensor is the gyration tensor and inertia the moment of inertia tensor
+// about the center, taking every particle as a unit mass.
+//
+// Eigenvalues holds the eigenvalues of the gyration tensor, the mean
+// squared extents along the principal axes, from largest to smallest. Axes
+// holds the principal axes in the same order, as unit vectors making up a
+// right handed frame. Flat aggregates have a third eigenvalue of 0 and a
+// third axis along z.
+//
+// Radius_of_gyration is the square root of the sum of the eigenvalues.
+//
+// Asphericity is the largest eigenvalue less the mean of the other two,
+// and acylindricity the difference between those two. Both are 0 for a
+// sphere.
+//
+// Anisotropy is the relative shape anisotropy, from 0 when the particles
+// are spread evenly in every direction to 1 when they lie on a line. A flat
+// aggregate spread evenly over the plane has 1 / 4.
+//
+// Axis_ratios holds the ratios of the second and third principal radii,
+// the square roots of the eigenvalues, to the first.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Shape {
+    pub center: Vec3d,
+    pub tensor: Matrix3,
+    pub inertia: Matrix3,
+    pub eigenvalues: [f64; 3],
+    pub axes: [Vec3d; 3],
+    pub radius_of_gyration: f64,
+    pub asphericity: f64,
+    pub acylindricity: f64,
+    pub anisotropy: f64,
+    pub axis_ratios: (f64, f64),
+}
+
+impl Shape {
+    pub fn new<A: Aggregation>(model: &A) -> Shape {
+        let points = model.positions();
+        let n = points.len().max(1) as f64;
+        let center = points.iter().fold(Vec3d::zero(), |sum, &p| sum + p) / n;
+
+        let mut tensor = [[0.; 3]; 3];
+        for p in &points {
+            let d = (*p - center).as_slice();
+            for (i, row) in tensor.iter_mut().enumerate() {
+                for (j, v) in row.iter_mut().enumerate() {
+                    *v += d[i] * d[j] / n;
+                }
+            }
+        }
+
+        let trace = tensor[0][0] + tensor[1][1] + tensor[2][2];
+        let mut inertia = [[0.; 3]; 3];
+        for (i, row) in inertia.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                let diagonal = if i == j { trace } else { 0. };
+                *v = (diagonal - tensor[i][j]) * points.len() as f64;
+            }
+        }
+
+        let (eigenvalues, axes) = principal_axes(tensor);
+        let [l1, l2, l3] = eigenvalues;
+        let sum = l1 + l2 + l3;
+        let (anisotropy, axis_ratios) = if l1 > 0. {
+            (
+                1. - 3. * (l1 * l2 + l2 * l3 + l3 * l1) / (sum * sum),
+                ((l2 / l1).sqrt(), (l3 / l1).sqrt()),
+            )
+        } else {
+            (0., (1., 1.))
+        };
+
+        Shape {
+            center,
+            tensor,
+            inertia,
+            eigenvalues,
+            axes,
+            radius_of_gyration: sum.sqrt(),
+            asphericity: l1 - (l2 + l3) / 2.,
+            acylindricity: l2 - l3,
+            anisotropy,
+            axis_ratios,
+        }
+    }
+
+    // The rotation that takes the principal axes to x, y and z, its rows are
+    // the axes
+    pub fn rotation(&self) -> Matrix3 {
+        [
+            self.axes[0].as_slice(),
+            self.axes[1].as_slice(),
+            self.axes[2].as_slice(),
+        ]
+    }
+
+    // Coordinates of p in the principal frame, around the center and along
+    // the principal axes
+    pub fn to_principal(&self, p: Vec3d) -> Vec3d {
+        apply3(self.rotation(), p - self.center)
+    }
+}
+
+// Eigenvalues of the symmetric m from largest to smallest and their unit
+// eigenvectors, found with Jacobi rotations. The vectors are turned to have
+// their largest coordinate positive, the third one to make a right handed
+// frame.
+fn principal_axes(mut m: Matrix3) -> ([f64; 3], [Vec3d; 3]) {
+    // columns are the eigenvectors
+    let mut v = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+    for _ in 0..50 {
+        let off = m[0][1] * m[0][1] + m[0][2] * m[0][2] + m[1][2] * m[1][2];
+        if off <= 1e-30 * (m[0][0] * m[0][0] + m[1][1] * m[1][1] + m[2][2] * m[2][2]) {
+            break;
+        }
+        for &(p, q) in &[(0, 1), (0, 2), (1, 2)] {
+            if m[p][q] == 0. {
+                continue;
+            }
+            // rotate by the angle that zeroes m[p][q]
+            let theta = (m[q][q] - m[p][p]) / (2. * m[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+            let c = 1. / (t * t + 1.).sqrt();
+            let s = t * c;
+            for row in m.iter_mut() {
+                let (a, b) = (row[p], row[q]);
+                row[p] = c * a - s * b;
+                row[q] = s * a + c * b;
+            }
+            let (row_p, row_q) = (m[p], m[q]);
+            for (k, (a, b)) in row_p.iter().zip(&row_q).enumerate() {
+                m[p][k] = c * a - s * b;
+                m[q][k] = s * a + c * b;
+            }
+            for row in v.iter_mut() {
+                let (a, b) = (row[p], row[q]);
+                row[p] = c * a - s * b;
+                row[q] = s * a + c * b;
+            }
+        }
+    }
+
+    let mut order = [0, 1, 2];
+    order.sort_by(|&a, &b| {
+        m[b][b]
+            .partial_cmp(&m[a][a])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let axis = |k: usize| {
+        let u = Vec3d::new(v[0][k], v[1][k], v[2][k]);
+        let largest = u
+            .as_slice()
+            .iter()
+            .copied()
+            .fold(0., |a: f64, x| if x.abs() > a.abs() { x } else { a });
+        if largest < 0. {
+            -u
+        } else {
+            u
+        }
+    };
+    let first = axis(order[0]);
+    let second = axis(order[1]);
+    (
+        [
+            m[order[0]][order[0]].max(0.),
+            m[order[1]][order[1]].max(0.),
+            m[order[2]][order[2]].max(0.),
+        ],
+        [first, second, first.cross(second)],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::structures::Vec2d;
+    use crate::symmetry::{apply2, conjugate3};
+    use crate::{FlatAggregation, Mask, Sticking, Symmetry2d};
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn principal_axes_of_a_turned_diagonal_tensor() {
+        // diag(4, 2, 1) turned by 30 degrees around z
+        let (sin, cos) = (PI / 6.).sin_cos();
+        let rotation = [[cos, -sin, 0.], [sin, cos, 0.], [0., 0., 1.]];
+        let tensor = conjugate3(rotation, [[4., 0., 0.], [0., 2., 0.], [0., 0., 1.]]);
+
+        let (eigenvalues, axes) = principal_axes(tensor);
+        assert!(
+            close(eigenvalues[0], 4.) && close(eigenvalues[1], 2.) && close(eigenvalues[2], 1.)
+        );
+        let expected = [
+            Vec3d::new(cos, sin, 0.),
+            Vec3d::new(-sin, cos, 0.),
+            Vec3d::new(0., 0., 1.),
+        ];
+        for (axis, expected) in axes.iter().zip(&expected) {
+            assert!(close(axis.distance(*expected), 0.), "{:?}", axes);
+        }
+    }
+
+    #[test]
+    fn straight_line_is_fully_anisotropic() {
+        let mut model = FlatAggregation::new();
+        for t in 0..10 {
+            model.add([t as f64, 2. * t as f64].into(), 0);
+        }
+        let shape = Shape::new(&model);
+        assert!(close(shape.anisotropy, 1.));
+        assert!(close(shape.axis_ratios.0, 0.) && close(shape.axis_ratios.1, 0.));
+        let along = Vec3d::new(1., 2., 0.).normalized();
+        assert!(close(shape.axes[0].distance(along), 0.));
+    }
+
+    #[test]
+    fn rotate_to_principal_turns_symmetry_and_sticking() {
+        let mut model = FlatAggregation::new();
+        for t in 0..10 {
+            model.add([t as f64, t as f64].into(), 0);
+        }
+        model.set_symmetry(Symmetry2d::Mirror);
+        let sticking = Sticking::Global(Vec3d::new(-1., 1., 0.), 1.);
+        model.set_constraints(crate::Constraints::new().with_sticking(sticking));
+
+        let angle = model.rotate_to_principal();
+        assert!(close(angle, -PI / 4.));
+        assert!(close(model.points[9].y, 0.) && model.points[9].x > 0.);
+
+        // the mirror now reflects across the turned y axis
+        let (sin, cos) = angle.sin_cos();
+        let turn = |p: Vec2d| Vec2d::new(cos * p.x - sin * p.y, sin * p.x + cos * p.y);
+        let p = Vec2d::new(3., 1.);
+        let mirrored = apply2(model.symmetry[0], turn(p));
+        assert!(close(mirrored.distance(turn(Vec2d::new(-3., 1.))), 0.));
+
+        match model.constraints.sticking() {
+            Sticking::Global(axis, _) => {
+                assert!(close(axis.distance(Vec3d::new(0., 2f64.sqrt(), 0.)), 0.))
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn rotate_to_principal_refuses_masks() {
+        let mut model = FlatAggregation::new();
+        model.add([0., 0.].into(), 0);
+        model.add([1., 1.].into(), 0);
+        model.set_stickiness_map(Mask::from_values(1, 1, vec![1.]));
+        assert!(matches!(
+            model.try_rotate_to_principal(),
+            Err(crate::Error::MasksNotRotatable)
+        ));
+        assert_eq!(model.points[1], Vec2d::new(1., 1.));
+    }
+}
@@ -0,0 +1,255 @@
+use std::f64::consts::PI;
+
+use crate::aggregation::Aggregation;
+
+// Topology describes the tree the parent links of an aggregate make, one
+// per seed.
+//
+// Strahler holds the Strahler order of every particle. Tips are of order
+// 1, and a particle is one order higher than its highest ordered children
+// when at least two of them share it, of that order otherwise.
+//
+// Horton holds the Horton order of every particle, where the highest order
+// of a stream carries on up to its source. At every junction it follows the
+// child of the highest Strahler order, of the larger subtree on a tie.
+//
+// Subtree_size holds the number of particles that lead to every particle
+// through their parents, itself included.
+//
+// Depth holds the distance from every particle to its seed, along the
+// tree.
+//
+// Branches holds the segments of the tree between seeds, junctions and
+// tips, see Branch, and branch_angles the angles in radians between every
+// two branches leaving the same junction.
+//
+// Tips is the number of particles nothing joined to, other than lone seeds.
+//
+// Streams holds the number of streams, unbroken runs of particles of the
+// same Strahler order, of every order from 1 on. Bifurcation_ratios holds
+// the ratio of the number of streams of every order to the one of the next
+// order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Topology {
+    pub strahler: Vec<usize>,
+    pub horton: Vec<usize>,
+    pub subtree_size: Vec<usize>,
+    pub depth: Vec<f64>,
+    pub branches: Vec<Branch>,
+    pub branch_angles: Vec<f64>,
+    pub tips: usize,
+    pub streams: Vec<usize>,
+    pub bifurcation_ratios: Vec<f64>,
+}
+
+// Branch is a segment of the tree from a seed or junction at start to the
+// next junction or tip at end, links particles long. Length is measured
+// along the tree and order is the Strahler order of the segment.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Branch {
+    pub start: usize,
+    pub end: usize,
+    pub links: usize,
+    pub length: f64,
+    pub order: usize,
+}
+
+impl Topology {
+    pub fn new<A: Aggregation>(model: &A) -> Topology {
+        let len = model.len();
+        let points = model.positions();
+        let parents = model.parents();
+
+        let mut children = vec![Vec::new(); len];
+        for (i, &parent) in parents.iter().enumerate() {
+            if parent != i {
+                children[parent].push(i);
+            }
+        }
+
+        // parents are always added before their children, so going
+        // backwards visits children first
+        let mut strahler = vec![1; len];
+        let mut subtree_size = vec![1; len];
+        for i in (0..len).rev() {
+            let mut highest = 0;
+            let mut ties = 0;
+            for &child in &children[i] {
+                subtree_size[i] += subtree_size[child];
+                if strahler[child] > highest {
+                    highest = strahler[child];
+                    ties = 1;
+                } else if strahler[child] == highest {
+                    ties += 1;
+                }
+            }
+            if highest > 0 {
+                strahler[i] = if ties > 1 { highest + 1 } else { highest };
+            }
+        }
+
+        let mut depth = vec![0.; len];
+        let mut horton = strahler.clone();
+        for i in 0..len {
+            let parent = parents[i];
+            if parent != i {
+                depth[i] = depth[parent] + points[i].distance(points[parent]);
+            }
+            let main = children[i]
+                .iter()
+                .max_by_key(|&&child| (strahler[child], subtree_size[child]));
+            if let Some(&main) = main {
+                horton[main] = horton[i];
+            }
+        }
+
+        let mut branches = Vec::new();
+        let mut branch_angles = Vec::new();
+        for start in 0..len {
+            if parents[start] != start && children[start].len() < 2 {
+                continue;
+            }
+            let first = branches.len();
+            for &child in &children[start] {
+                let mut end = child;
+                let mut links = 1;
+                while children[end].len() == 1 {
+                    end = children[end][0];
+                    links += 1;
+                }
+                branches.push(Branch {
+                    start,
+                    end,
+                    links,
+                    length: depth[end] - depth[start],
+                    order: strahler[child],
+                });
+            }
+
+            let leaving = &branches[first..];
+            for (k, a) in leaving.iter().enumerate() {
+                for b in &leaving[k + 1..] {
+                    let u = (points[a.end] - points[start]).normalized();
+                    let v = (points[b.end] - points[start]).normalized();
+                    branch_angles.push(u.dot(v).clamp(-1., 1.).acos());
+                }
+            }
+        }
+
+        let tips = (0..len)
+            .filter(|&i| children[i].is_empty() && parents[i] != i)
+            .count();
+
+        let highest = strahler.iter().copied().max().unwrap_or(0);
+        let mut streams = vec![0; highest];
+        for i in 0..len {
+            let parent = parents[i];
+            if parent == i || strahler[parent] != strahler[i] {
+                streams[strahler[i] - 1] += 1;
+            }
+        }
+        let bifurcation_ratios = streams
+            .windows(2)
+            .map(|w| w[0] as f64 / w[1] as f64)
+            .collect();
+
+        Topology {
+            strahler,
+            horton,
+            subtree_size,
+            depth,
+            branches,
+            branch_angles,
+            tips,
+            streams,
+            bifurcation_ratios,
+        }
+    }
+
+    // Counts branch_angles in bins equal parts of 0 to pi
+    pub fn angle_histogram(&self, bins: usize) -> Vec<usize> {
+        let mut histogram = vec![0; bins];
+        if bins == 0 {
+            return histogram;
+        }
+        for &angle in &self.branch_angles {
+            let bin = (angle / PI * bins as f64) as usize;
+            histogram[bin.min(bins - 1)] += 1;
+        }
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FlatAggregation;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    // A seed at 0 with a tip 6 on one side, and a run on the other that
+    // forks at 1 into the tip 2 and into 3, which forks again into 4 and 5
+    //
+    //          2
+    //         /
+    //  6 - 0 - 1
+    //           \
+    //            3 - 4
+    //            |
+    //            5
+    fn forked() -> FlatAggregation {
+        let mut model = FlatAggregation::new();
+        model.add_seed([0., 0.].into());
+        model.add([1., 0.].into(), 0);
+        model.add([2., 1.].into(), 1);
+        model.add([2., -1.].into(), 1);
+        model.add([3., -1.].into(), 3);
+        model.add([3., -2.].into(), 3);
+        model.add([-1., 0.].into(), 0);
+        model
+    }
+
+    #[test]
+    fn orders_of_a_forked_tree() {
+        let topology = Topology::new(&forked());
+        assert_eq!(topology.strahler, vec![2, 2, 1, 2, 1, 1, 1]);
+        // the tie at 3 goes to the last of its equal children
+        assert_eq!(topology.horton, vec![2, 2, 1, 2, 1, 2, 1]);
+        assert_eq!(topology.subtree_size, vec![7, 5, 1, 3, 1, 1, 1]);
+        assert_eq!(topology.tips, 4);
+        assert_eq!(topology.streams, vec![4, 1]);
+        assert_eq!(topology.bifurcation_ratios, vec![4.]);
+    }
+
+    #[test]
+    fn branches_of_a_forked_tree() {
+        let topology = Topology::new(&forked());
+        let diagonal = 2f64.sqrt();
+        assert!(close(topology.depth[4], 2. + diagonal));
+        assert!(close(topology.depth[6], 1.));
+
+        let ends: Vec<_> = topology
+            .branches
+            .iter()
+            .map(|b| (b.start, b.end, b.links, b.order))
+            .collect();
+        assert_eq!(
+            ends,
+            vec![
+                (0, 1, 1, 2),
+                (0, 6, 1, 1),
+                (1, 2, 1, 1),
+                (1, 3, 1, 2),
+                (3, 4, 1, 1),
+                (3, 5, 1, 1),
+            ]
+        );
+        assert!(close(topology.branches[3].length, diagonal));
+
+        let angles = &topology.branch_angles;
+        assert!(close(angles[0], PI) && close(angles[1], PI / 2.) && close(angles[2], PI / 4.));
+        assert_eq!(topology.angle_histogram(4), vec![0, 1, 1, 1]);
+    }
+}
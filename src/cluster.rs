@@ -0,0 +1,18 @@
+// ClusterStats summarizes the particles grown from one seed, see
+// cluster_stats on the aggregations.
+//
+// Seed is the index of the seed particle and particles the number of
+// particles in the cluster, the seed included.
+//
+// Center is their centroid and radius_of_gyration their root mean square
+// distance from it.
+//
+// Extent is the distance from the seed to the furthest of them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClusterStats<V> {
+    pub seed: usize,
+    pub particles: usize,
+    pub center: V,
+    pub radius_of_gyration: f64,
+    pub extent: f64,
+}
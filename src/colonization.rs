@@ -0,0 +1,496 @@
+use rstar::RTree;
+
+use crate::aggregation::Aggregation;
+use crate::error::{Error, Result};
+use crate::sink::{self, ParticleSink};
+use crate::structures::{vec2d, IndexValue2d, IndexValue3d, Nearest, SpatialIndex, Vec2d, Vec3d};
+use crate::DEFAULT_PARTICLE_SPACING;
+
+const DEFAULT_INFLUENCE_DISTANCE: f64 = 8.;
+const DEFAULT_KILL_DISTANCE: f64 = 2.;
+
+// FlatColonization and ConvexColonization grow trees by space
+// colonization instead of random walks. Attractors are scattered over the
+// volume the tree should fill. Every step, each attractor pulls on the
+// node closest to it, if that one is within influence_distance, and every
+// node pulled on grows a new node particle_spacing away, towards the mean
+// direction of its attractors. Attractors within kill_distance of a node
+// are reached and removed. Growth ends once no node is pulled on anymore,
+// so roots need attractors within influence_distance to start from.
+//
+// Nodes are kept like the particles of FlatAggregation and
+// ConvexAggregation, in points, parents and index, so the same Raycaster,
+// save_csv and sinks apply. Both implement Aggregation, so the analyses
+// take them too.
+pub struct FlatColonization<I = RTree<IndexValue2d>> {
+    particle_spacing: f64,
+    influence_distance: f64,
+    kill_distance: f64,
+    attractors: Vec<Vec2d>,
+    pub points: Vec<Vec2d>,
+    pub parents: Vec<usize>,
+    pub index: I,
+    sink: Option<Box<dyn ParticleSink>>,
+    sink_error: Option<std::io::Error>,
+}
+
+impl FlatColonization {
+    pub fn new() -> FlatColonization {
+        FlatColonization::with_index(RTree::new())
+    }
+}
+
+impl Default for FlatColonization {
+    fn default() -> FlatColonization {
+        FlatColonization::new()
+    }
+}
+
+impl<I: SpatialIndex<Vec2d>> FlatColonization<I> {
+    pub fn with_index(index: I) -> FlatColonization<I> {
+        FlatColonization {
+            particle_spacing: DEFAULT_PARTICLE_SPACING,
+            influence_distance: DEFAULT_INFLUENCE_DISTANCE,
+            kill_distance: DEFAULT_KILL_DISTANCE,
+            attractors: Vec::new(),
+            points: Vec::new(),
+            parents: Vec::new(),
+            index,
+            sink: None,
+            sink_error: None,
+        }
+    }
+
+    pub fn set_particle_spacing(&mut self, a: f64) {
+        self.particle_spacing = a;
+    }
+
+    pub fn set_influence_distance(&mut self, a: f64) {
+        self.influence_distance = a;
+    }
+
+    pub fn set_kill_distance(&mut self, a: f64) {
+        self.kill_distance = a;
+    }
+
+    pub fn set_sink<S: ParticleSink + Send + 'static>(&mut self, a: S) {
+        self.sink = Some(Box::new(a));
+        self.sink_error = None;
+    }
+
+    pub fn take_sink(&mut self) -> Option<Box<dyn ParticleSink>> {
+        self.sink.take()
+    }
+
+    pub fn sink_error(&self) -> Option<&std::io::Error> {
+        self.sink_error.as_ref()
+    }
+
+    // The attractors that haven't been reached yet
+    pub fn attractors(&self) -> &[Vec2d] {
+        &self.attractors
+    }
+
+    pub fn add_attractor(&mut self, p: Vec2d) {
+        self.attractors.push(p);
+    }
+
+    // Adds count attractors spread evenly over a disc of radius around the
+    // origin
+    pub fn scatter_attractors(&mut self, count: usize, radius: f64) {
+        for _ in 0..count {
+            self.attractors
+                .push(vec2d::random_in_unit_sphere() * radius);
+        }
+    }
+
+    // Adds p as a root of its own. Growing from several roots needs them
+    // added this way, add(p, 0) joins p to the first node once there is
+    // one.
+    pub fn add_seed(&mut self, p: Vec2d) {
+        self.add(p, usize::MAX);
+    }
+
+    // A parent that isn't an already added node makes p a root
+    pub fn add(&mut self, p: Vec2d, parent: usize) {
+        let id = self.points.len();
+        let parent = if parent < id { parent } else { id };
+        self.index.insert(p, id);
+        self.points.push(p);
+        self.parents.push(parent);
+        sink::emit(
+            &mut self.sink,
+            &mut self.sink_error,
+            id,
+            parent,
+            [p.x, p.y, 0.],
+        );
+    }
+
+    // Grows every node pulled on by one node, returns the number of nodes
+    // added
+    pub fn grow_step(&mut self) -> usize {
+        self.try_grow_step().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like grow_step, but returns Error::Empty instead of panicking when
+    // there is no root yet
+    pub fn try_grow_step(&mut self) -> Result<usize> {
+        if self.points.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        let mut pulls = vec![Vec2d::zero(); self.points.len()];
+        let (index, points) = (&self.index, &self.points);
+        let (influence, kill) = (self.influence_distance, self.kill_distance);
+        self.attractors
+            .retain(|a| match index.nearest(a, influence) {
+                Nearest::Particle(_, d) if d < kill => false,
+                Nearest::Particle(i, d) if d < influence => {
+                    pulls[i] += (*a - points[i]) / d;
+                    true
+                }
+                _ => true,
+            });
+
+        let start = self.points.len();
+        for (parent, pull) in pulls.into_iter().enumerate() {
+            // attractors on opposite sides cancel out
+            if pull.length() < 1e-9 {
+                continue;
+            }
+            let p = self.points[parent] + pull.normalized() * self.particle_spacing;
+            if let Nearest::Particle(_, d) = self.index.nearest(&p, self.particle_spacing) {
+                if d < self.particle_spacing / 2. {
+                    continue;
+                }
+            }
+            self.add(p, parent);
+        }
+        Ok(self.points.len() - start)
+    }
+
+    // Runs grow_step until nothing grows or for iterations steps at most,
+    // returns the number of nodes added
+    pub fn grow(&mut self, iterations: usize) -> usize {
+        self.try_grow(iterations)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_grow(&mut self, iterations: usize) -> Result<usize> {
+        let start = self.points.len();
+        for _ in 0..iterations {
+            if self.try_grow_step()? == 0 {
+                break;
+            }
+        }
+        Ok(self.points.len() - start)
+    }
+
+    pub fn save_csv(&self, name: &str) -> std::io::Result<()> {
+        println!("Saving to csv");
+        use std::fs::File;
+        use std::io::prelude::*;
+
+        let mut file = File::create(name)?;
+        file.write_all(b"index,parent,x,y,z\n")?;
+
+        for (index, (val, parent)) in self.points.iter().zip(&self.parents).enumerate() {
+            writeln!(
+                file,
+                "{},{},{:.04},{:.04},{:.04}",
+                index, parent, val.x, val.y, 0.
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<I: SpatialIndex<Vec2d>> Aggregation for FlatColonization<I> {
+    type Vector = Vec2d;
+
+    fn add(&mut self, p: Vec2d, parent: usize) {
+        self.add(p, parent)
+    }
+
+    // Colonization grows every node pulled on at once, so this is a whole
+    // grow_step, which adds nothing once growth has ended
+    fn add_particle(&mut self) {
+        self.grow_step();
+    }
+
+    fn try_add_particle(&mut self) -> Result<()> {
+        self.try_grow_step().map(|_| ())
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn dimensions(&self) -> usize {
+        2
+    }
+
+    fn position(&self, index: usize) -> Vec3d {
+        Vec3d::from(self.points[index])
+    }
+
+    fn parent(&self, index: usize) -> usize {
+        self.parents[index]
+    }
+
+    fn within(&self, p: Vec3d, radius: f64) -> Vec<usize> {
+        self.index.within(&Vec2d::new(p.x, p.y), radius)
+    }
+
+    fn particle_spacing(&self) -> f64 {
+        self.particle_spacing
+    }
+
+    // Nodes have no attraction_distance, kill_distance is the closest an
+    // attractor gets before it is reached
+    fn attraction_distance(&self) -> f64 {
+        self.kill_distance
+    }
+
+    fn bounding_radius(&self) -> f64 {
+        self.points.iter().map(|p| p.length()).fold(0., f64::max)
+    }
+}
+
+pub struct ConvexColonization<I = RTree<IndexValue3d>> {
+    particle_spacing: f64,
+    influence_distance: f64,
+    kill_distance: f64,
+    attractors: Vec<Vec3d>,
+    pub points: Vec<Vec3d>,
+    pub parents: Vec<usize>,
+    pub index: I,
+    sink: Option<Box<dyn ParticleSink>>,
+    sink_error: Option<std::io::Error>,
+}
+
+impl ConvexColonization {
+    pub fn new() -> ConvexColonization {
+        ConvexColonization::with_index(RTree::new())
+    }
+}
+
+impl Default for ConvexColonization {
+    fn default() -> ConvexColonization {
+        ConvexColonization::new()
+    }
+}
+
+impl<I: SpatialIndex<Vec3d>> ConvexColonization<I> {
+    pub fn with_index(index: I) -> ConvexColonization<I> {
+        ConvexColonization {
+            particle_spacing: DEFAULT_PARTICLE_SPACING,
+            influence_distance: DEFAULT_INFLUENCE_DISTANCE,
+            kill_distance: DEFAULT_KILL_DISTANCE,
+            attractors: Vec::new(),
+            points: Vec::new(),
+            parents: Vec::new(),
+            index,
+            sink: None,
+            sink_error: None,
+        }
+    }
+
+    pub fn set_particle_spacing(&mut self, a: f64) {
+        self.particle_spacing = a;
+    }
+
+    pub fn set_influence_distance(&mut self, a: f64) {
+        self.influence_distance = a;
+    }
+
+    pub fn set_kill_distance(&mut self, a: f64) {
+        self.kill_distance = a;
+    }
+
+    pub fn set_sink<S: ParticleSink + Send + 'static>(&mut self, a: S) {
+        self.sink = Some(Box::new(a));
+        self.sink_error = None;
+    }
+
+    pub fn take_sink(&mut self) -> Option<Box<dyn ParticleSink>> {
+        self.sink.take()
+    }
+
+    pub fn sink_error(&self) -> Option<&std::io::Error> {
+        self.sink_error.as_ref()
+    }
+
+    // The attractors that haven't been reached yet
+    pub fn attractors(&self) -> &[Vec3d] {
+        &self.attractors
+    }
+
+    pub fn add_attractor(&mut self, p: Vec3d) {
+        self.attractors.push(p);
+    }
+
+    // Adds count attractors spread evenly over a ball of radius around the
+    // origin
+    pub fn scatter_attractors(&mut self, count: usize, radius: f64) {
+        for _ in 0..count {
+            self.attractors.push(Vec3d::random() * radius);
+        }
+    }
+
+    // Adds p as a root of its own. Growing from several roots needs them
+    // added this way, add(p, 0) joins p to the first node once there is
+    // one.
+    pub fn add_seed(&mut self, p: Vec3d) {
+        self.add(p, usize::MAX);
+    }
+
+    // A parent that isn't an already added node makes p a root
+    pub fn add(&mut self, p: Vec3d, parent: usize) {
+        let id = self.points.len();
+        let parent = if parent < id { parent } else { id };
+        self.index.insert(p, id);
+        self.points.push(p);
+        self.parents.push(parent);
+        sink::emit(
+            &mut self.sink,
+            &mut self.sink_error,
+            id,
+            parent,
+            [p.x, p.y, p.z],
+        );
+    }
+
+    // Grows every node pulled on by one node, returns the number of nodes
+    // added
+    pub fn grow_step(&mut self) -> usize {
+        self.try_grow_step().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like grow_step, but returns Error::Empty instead of panicking when
+    // there is no root yet
+    pub fn try_grow_step(&mut self) -> Result<usize> {
+        if self.points.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        let mut pulls = vec![Vec3d::zero(); self.points.len()];
+        let (index, points) = (&self.index, &self.points);
+        let (influence, kill) = (self.influence_distance, self.kill_distance);
+        self.attractors
+            .retain(|a| match index.nearest(a, influence) {
+                Nearest::Particle(_, d) if d < kill => false,
+                Nearest::Particle(i, d) if d < influence => {
+                    pulls[i] += (*a - points[i]) / d;
+                    true
+                }
+                _ => true,
+            });
+
+        let start = self.points.len();
+        for (parent, pull) in pulls.into_iter().enumerate() {
+            // attractors on opposite sides cancel out
+            if pull.length() < 1e-9 {
+                continue;
+            }
+            let p = self.points[parent] + pull.normalized() * self.particle_spacing;
+            if let Nearest::Particle(_, d) = self.index.nearest(&p, self.particle_spacing) {
+                if d < self.particle_spacing / 2. {
+                    continue;
+                }
+            }
+            self.add(p, parent);
+        }
+        Ok(self.points.len() - start)
+    }
+
+    // Runs grow_step until nothing grows or for iterations steps at most,
+    // returns the number of nodes added
+    pub fn grow(&mut self, iterations: usize) -> usize {
+        self.try_grow(iterations)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_grow(&mut self, iterations: usize) -> Result<usize> {
+        let start = self.points.len();
+        for _ in 0..iterations {
+            if self.try_grow_step()? == 0 {
+                break;
+            }
+        }
+        Ok(self.points.len() - start)
+    }
+
+    pub fn save_csv(&self, name: &str) -> std::io::Result<()> {
+        println!("Saving to csv");
+        use std::fs::File;
+        use std::io::prelude::*;
+
+        let mut file = File::create(name)?;
+        file.write_all(b"index,parent,x,y,z\n")?;
+
+        for (index, (val, parent)) in self.points.iter().zip(&self.parents).enumerate() {
+            writeln!(
+                file,
+                "{},{},{:.04},{:.04},{:.04}",
+                index, parent, val.x, val.y, val.z
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<I: SpatialIndex<Vec3d>> Aggregation for ConvexColonization<I> {
+    type Vector = Vec3d;
+
+    fn add(&mut self, p: Vec3d, parent: usize) {
+        self.add(p, parent)
+    }
+
+    // Colonization grows every node pulled on at once, so this is a whole
+    // grow_step, which adds nothing once growth has ended
+    fn add_particle(&mut self) {
+        self.grow_step();
+    }
+
+    fn try_add_particle(&mut self) -> Result<()> {
+        self.try_grow_step().map(|_| ())
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn dimensions(&self) -> usize {
+        3
+    }
+
+    fn position(&self, index: usize) -> Vec3d {
+        self.points[index]
+    }
+
+    fn parent(&self, index: usize) -> usize {
+        self.parents[index]
+    }
+
+    fn within(&self, p: Vec3d, radius: f64) -> Vec<usize> {
+        self.index.within(&p, radius)
+    }
+
+    fn particle_spacing(&self) -> f64 {
+        self.particle_spacing
+    }
+
+    // Nodes have no attraction_distance, kill_distance is the closest an
+    // attractor gets before it is reached
+    fn attraction_distance(&self) -> f64 {
+        self.kill_distance
+    }
+
+    fn bounding_radius(&self) -> f64 {
+        self.points.iter().map(|p| p.length()).fold(0., f64::max)
+    }
+}
@@ -0,0 +1,344 @@
+use rand::Rng;
+
+use crate::aggregation::Aggregation;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::far_field::{self, FarField};
+use crate::sink::{self, ParticleSink};
+use crate::structures::grid::Grid;
+use crate::structures::{vec3d, Nearest, Vec3d};
+use crate::{
+    DEFAULT_ATTRACTION_DISTANCE, DEFAULT_MIN_MOVE_DISTANCE, DEFAULT_PARTICLE_SPACING,
+    DEFAULT_STICKINESS, DEFAULT_STUBBORNNESS,
+};
+
+// Cells hold a handful of particles each, so the per cell bookkeeping of
+// the grid is spread over several of them
+const DEFAULT_CELL_SIZE: f64 = 6.;
+
+// CompactAggregation grows the same aggregates as ConvexAggregation in less
+// memory, for runs of tens of millions of particles.
+//
+// Points stores every position once, in single precision. The index is a
+// hashed grid that only keeps particle ids and looks positions up in
+// points, instead of holding a second copy of them.
+//
+// Parents and join_attempts take a u32 per particle rather than a usize,
+// join attempts saturate at u32::MAX.
+//
+// The growth parameters mean the same as in ConvexAggregation.
+pub struct CompactAggregation {
+    particle_spacing: f64,
+    attraction_distance: f64,
+    min_move_distanse: f64,
+    stubbornness: u32,
+    stickiness: f64,
+    bounding_radius: f64,
+    far_field: FarField,
+    join_attempts: Vec<u32>,
+    pub points: Vec<[f32; 3]>,
+    pub parents: Vec<u32>,
+    index: Grid<3, u32>,
+    sink: Option<Box<dyn ParticleSink>>,
+    sink_error: Option<std::io::Error>,
+}
+
+impl Default for CompactAggregation {
+    fn default() -> CompactAggregation {
+        CompactAggregation::new()
+    }
+}
+
+impl CompactAggregation {
+    pub fn new() -> CompactAggregation {
+        CompactAggregation::with_cell_size(DEFAULT_CELL_SIZE)
+    }
+
+    pub fn with_cell_size(cell_size: f64) -> CompactAggregation {
+        CompactAggregation {
+            particle_spacing: DEFAULT_PARTICLE_SPACING,
+            attraction_distance: DEFAULT_ATTRACTION_DISTANCE,
+            min_move_distanse: DEFAULT_MIN_MOVE_DISTANCE,
+            stubbornness: DEFAULT_STUBBORNNESS as u32,
+            stickiness: DEFAULT_STICKINESS,
+            bounding_radius: 0.,
+            far_field: FarField::default(),
+            points: Vec::new(),
+            parents: Vec::new(),
+            join_attempts: Vec::new(),
+            index: Grid::new(cell_size),
+            sink: None,
+            sink_error: None,
+        }
+    }
+
+    pub fn set_particle_spacing(&mut self, a: f64) {
+        self.particle_spacing = a;
+    }
+
+    pub fn set_attraction_distance(&mut self, a: f64) {
+        self.attraction_distance = a;
+    }
+
+    pub fn set_min_move_distance(&mut self, a: f64) {
+        self.min_move_distanse = a;
+    }
+
+    pub fn set_stubbornness(&mut self, a: usize) {
+        self.stubbornness = a.min(u32::MAX as usize) as u32;
+    }
+
+    pub fn set_stickness(&mut self, a: f64) {
+        self.stickiness = a;
+    }
+
+    // Sets every growth parameter at once from a checked Config
+    pub fn set_config(&mut self, a: Config) {
+        self.set_particle_spacing(a.particle_spacing());
+        self.set_attraction_distance(a.attraction_distance());
+        self.set_min_move_distance(a.min_move_distance());
+        self.set_stubbornness(a.stubbornness());
+        self.set_stickness(a.stickiness());
+    }
+
+    pub fn set_far_field(&mut self, a: FarField) {
+        self.far_field = a;
+    }
+
+    pub fn set_sink<S: ParticleSink + Send + 'static>(&mut self, a: S) {
+        self.sink = Some(Box::new(a));
+        self.sink_error = None;
+    }
+
+    pub fn take_sink(&mut self) -> Option<Box<dyn ParticleSink>> {
+        self.sink.take()
+    }
+
+    pub fn sink_error(&self) -> Option<&std::io::Error> {
+        self.sink_error.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn position(&self, index: usize) -> Vec3d {
+        let [x, y, z] = self.points[index];
+        Vec3d::new(x, y, z)
+    }
+
+    pub fn random_starting_position(&self) -> Vec3d {
+        let d = self.bounding_radius;
+        vec3d::Vec3d::random().normalized() * d
+    }
+
+    fn should_reset(&self, p: Vec3d) -> bool {
+        p.length() > self.bounding_radius * 2.
+    }
+
+    fn return_to_launch_sphere(&self, p: Vec3d) -> Vec3d {
+        match self.far_field {
+            FarField::Reset => self.random_starting_position(),
+            FarField::Return => far_field::return_to_sphere(p, self.bounding_radius)
+                .unwrap_or_else(|| self.random_starting_position()),
+        }
+    }
+
+    fn should_join(&mut self, parent: usize) -> bool {
+        self.join_attempts[parent] = self.join_attempts[parent].saturating_add(1);
+        if self.join_attempts[parent] < self.stubbornness {
+            return false;
+        }
+        rand::thread_rng().gen_range(0., 1.) <= self.stickiness
+    }
+
+    fn place_particle(&self, p: Vec3d, parent: usize) -> Vec3d {
+        vec3d::lerp(self.position(parent), p, self.particle_spacing)
+    }
+
+    fn motion_vector(&self) -> Vec3d {
+        vec3d::Vec3d::random()
+    }
+
+    // Adds p as a seed of its own. Growing from several seeds needs them
+    // added this way, add(p, 0) joins p to the first particle once there is
+    // one.
+    pub fn add_seed(&mut self, p: Vec3d) {
+        self.add(p, usize::MAX);
+    }
+
+    // A parent that isn't an already added particle makes p a seed. Panics
+    // when there is no id left for it.
+    pub fn add(&mut self, p: Vec3d, parent: usize) {
+        if let Err(e) = self.try_add(p, parent) {
+            panic!("{}", e);
+        }
+    }
+
+    // Like add, but returns an Error instead of panicking
+    pub fn try_add(&mut self, p: Vec3d, parent: usize) -> Result<()> {
+        if self.points.len() >= u32::MAX as usize {
+            return Err(Error::TooManyParticles);
+        }
+        let id = self.points.len() as u32;
+        let parent = if parent < id as usize {
+            parent as u32
+        } else {
+            id
+        };
+        let point = [p.x as f32, p.y as f32, p.z as f32];
+        // bucket the rounded position, that's the one queries will see
+        self.index.insert(point.map(f64::from), id);
+        self.points.push(point);
+        self.parents.push(parent);
+        self.join_attempts.push(0);
+        sink::emit(
+            &mut self.sink,
+            &mut self.sink_error,
+            id as usize,
+            parent as usize,
+            point.map(f64::from),
+        );
+        self.bounding_radius = self
+            .bounding_radius
+            .max(p.length() + self.attraction_distance);
+        Ok(())
+    }
+
+    // See ConvexAggregation::search_radius
+    fn search_radius(&self) -> f64 {
+        self.attraction_distance * 2.
+    }
+
+    fn nearest(&self, p: &Vec3d) -> Nearest {
+        let points = &self.points;
+        self.index
+            .nearest(&p.as_slice(), self.search_radius(), &|&id| {
+                let [x, y, z] = points[id as usize];
+                ([f64::from(x), f64::from(y), f64::from(z)], id as usize)
+            })
+    }
+
+    pub fn add_particle(&mut self) {
+        if let Err(e) = self.try_add_particle() {
+            panic!("{}", e);
+        }
+    }
+
+    // Like add_particle, but returns an Error instead of panicking when
+    // there is nothing to grow from or no id left for another particle
+    pub fn try_add_particle(&mut self) -> Result<()> {
+        if self.points.is_empty() {
+            return Err(Error::Empty);
+        }
+        if self.points.len() >= u32::MAX as usize {
+            return Err(Error::TooManyParticles);
+        }
+
+        let mut p = self.random_starting_position();
+
+        loop {
+            let d = match self.nearest(&p) {
+                Nearest::Particle(parent, d) if d < self.attraction_distance => {
+                    if !self.should_join(parent) {
+                        // push particle away a bit
+                        p = vec3d::lerp(
+                            self.position(parent),
+                            p,
+                            self.attraction_distance + self.min_move_distanse,
+                        );
+                        continue;
+                    }
+
+                    // adjust particle position in relation to its parent
+                    p = self.place_particle(p, parent);
+
+                    // add the point
+                    self.add(p, parent);
+                    return Ok(());
+                }
+                Nearest::Particle(_, d) | Nearest::Clear(d) => d,
+            };
+
+            // move randomly
+            let m = self.min_move_distanse.max(d - self.attraction_distance);
+            p += self.motion_vector().normalized() * m;
+
+            // check if particle is too far away, bring it back if so
+            if self.should_reset(p) {
+                p = self.return_to_launch_sphere(p);
+            }
+        }
+    }
+
+    pub fn save_csv(&self, name: &str) -> std::io::Result<()> {
+        println!("Saving to csv");
+        use std::fs::File;
+        use std::io::prelude::*;
+
+        let mut file = std::io::BufWriter::new(File::create(name)?);
+        file.write_all(b"index,parent,x,y,z\n")?;
+
+        for (index, ([x, y, z], parent)) in self.points.iter().zip(&self.parents).enumerate() {
+            writeln!(file, "{},{},{:.04},{:.04},{:.04}", index, parent, x, y, z)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Aggregation for CompactAggregation {
+    type Vector = Vec3d;
+
+    fn add(&mut self, p: Vec3d, parent: usize) {
+        self.add(p, parent)
+    }
+
+    fn add_particle(&mut self) {
+        self.add_particle()
+    }
+
+    fn try_add_particle(&mut self) -> Result<()> {
+        self.try_add_particle()
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn dimensions(&self) -> usize {
+        3
+    }
+
+    fn position(&self, index: usize) -> Vec3d {
+        CompactAggregation::position(self, index)
+    }
+
+    fn parent(&self, index: usize) -> usize {
+        self.parents[index] as usize
+    }
+
+    fn within(&self, p: Vec3d, radius: f64) -> Vec<usize> {
+        let points = &self.points;
+        self.index.within(&p.as_slice(), radius, &|&id| {
+            let [x, y, z] = points[id as usize];
+            ([f64::from(x), f64::from(y), f64::from(z)], id as usize)
+        })
+    }
+
+    fn particle_spacing(&self) -> f64 {
+        self.particle_spacing
+    }
+
+    fn attraction_distance(&self) -> f64 {
+        self.attraction_distance
+    }
+
+    fn bounding_radius(&self) -> f64 {
+        self.bounding_radius
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+
+use crate::error::{Error, Result};
+use crate::structures::Vec3d;
+use crate::{
+    DEFAULT_ATTRACTION_DISTANCE, DEFAULT_MIN_MOVE_DISTANCE, DEFAULT_PARTICLE_SPACING,
+    DEFAULT_STICKINESS, DEFAULT_STUBBORNNESS,
+};
+
+// Config is a set of growth parameters that has been checked to make
+// sense, see Invalid. It is only made by ConfigBuilder::build and can be
+// handed to flat, convex, compact and surface aggregations with
+// set_config, as often as needed. Space colonization doesn't catch walkers
+// and has parameters of its own.
+//
+// The parameters mean the same as in Builder.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Config {
+    particle_spacing: f64,
+    attraction_distance: f64,
+    min_move_distance: f64,
+    stubbornness: usize,
+    stickiness: f64,
+}
+
+// Invalid is a growth parameter ConfigBuilder::build turned down, with its
+// value.
+//
+// ParticleSpacing isn't positive.
+//
+// AttractionDistance is smaller than particle_spacing, walkers would be
+// placed further away than where they were caught.
+//
+// MinMoveDistance isn't positive, walkers next to the aggregate could
+// stall.
+//
+// Stickiness is outside of 0 to 1, or 0, where no walker would ever stick
+// and growth would never end.
+//
+// Any of them is also turned down when it isn't finite.
+//
+// MaxChildren, StickingAxis and StickingAngle are Constraints that
+// try_set_constraints turned down, as no walker could ever join under
+// them. MaxChildren is 0, StickingAxis is a Sticking::Global axis without a
+// direction and StickingAngle a sticking cone that isn't open.
+//
+// ScheduleValue is a value of Schedule::try_exponential that isn't
+// positive, and Keyframes means Schedule::try_keyframes got none.
+//
+// MaskSize is the number of values Mask::try_from_values got when it
+// doesn't match the size of the mask, and Channel a channel
+// Mask::try_from_channel doesn't have.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Invalid {
+    ParticleSpacing(f64),
+    AttractionDistance(f64),
+    MinMoveDistance(f64),
+    Stickiness(f64),
+    MaxChildren(usize),
+    StickingAxis(Vec3d),
+    StickingAngle(f64),
+    ScheduleValue(f64),
+    Keyframes,
+    MaskSize(usize),
+    Channel(usize),
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Invalid::ParticleSpacing(a) => write!(f, "particle_spacing {} isn't positive", a),
+            Invalid::AttractionDistance(a) => write!(
+                f,
+                "attraction_distance {} is smaller than particle_spacing",
+                a
+            ),
+            Invalid::MinMoveDistance(a) => write!(f, "min_move_distance {} isn't positive", a),
+            Invalid::Stickiness(a) => write!(f, "stickiness {} isn't above 0 and at most 1", a),
+            Invalid::MaxChildren(a) => write!(f, "max_children {} leaves nowhere to grow", a),
+            Invalid::StickingAxis(a) => write!(f, "sticking axis {:?} has no direction", a),
+            Invalid::StickingAngle(a) => write!(f, "sticking angle {} isn't positive", a),
+            Invalid::ScheduleValue(a) => {
+                write!(f, "exponential schedule value {} isn't positive", a)
+            }
+            Invalid::Keyframes => write!(f, "schedule needs at least one keyframe"),
+            Invalid::MaskSize(a) => write!(f, "{} values don't fill the mask", a),
+            Invalid::Channel(a) => write!(f, "images have no channel {}", a),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            particle_spacing: DEFAULT_PARTICLE_SPACING,
+            attraction_distance: DEFAULT_ATTRACTION_DISTANCE,
+            min_move_distance: DEFAULT_MIN_MOVE_DISTANCE,
+            stubbornness: DEFAULT_STUBBORNNESS,
+            stickiness: DEFAULT_STICKINESS,
+        }
+    }
+}
+
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    pub fn particle_spacing(&self) -> f64 {
+        self.particle_spacing
+    }
+
+    pub fn attraction_distance(&self) -> f64 {
+        self.attraction_distance
+    }
+
+    pub fn min_move_distance(&self) -> f64 {
+        self.min_move_distance
+    }
+
+    pub fn stubbornness(&self) -> usize {
+        self.stubbornness
+    }
+
+    pub fn stickiness(&self) -> f64 {
+        self.stickiness
+    }
+}
+
+// ConfigBuilder collects growth parameters, starting from the defaults,
+// and checks them all at once in build
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn with_particle_spacing(mut self, a: f64) -> ConfigBuilder {
+        self.config.particle_spacing = a;
+        self
+    }
+
+    pub fn with_attraction_distance(mut self, a: f64) -> ConfigBuilder {
+        self.config.attraction_distance = a;
+        self
+    }
+
+    pub fn with_min_move_distance(mut self, a: f64) -> ConfigBuilder {
+        self.config.min_move_distance = a;
+        self
+    }
+
+    pub fn with_stubbornness(mut self, a: usize) -> ConfigBuilder {
+        self.config.stubbornness = a;
+        self
+    }
+
+    pub fn with_stickiness(mut self, a: f64) -> ConfigBuilder {
+        self.config.stickiness = a;
+        self
+    }
+
+    // The Config, or Error::Config with every parameter that is off
+    pub fn build(self) -> Result<Config> {
+        let c = self.config;
+        let mut invalid = Vec::new();
+
+        if !(c.particle_spacing.is_finite() && c.particle_spacing > 0.) {
+            invalid.push(Invalid::ParticleSpacing(c.particle_spacing));
+        }
+        if !(c.attraction_distance.is_finite() && c.attraction_distance >= c.particle_spacing) {
+            invalid.push(Invalid::AttractionDistance(c.attraction_distance));
+        }
+        if !(c.min_move_distance.is_finite() && c.min_move_distance > 0.) {
+            invalid.push(Invalid::MinMoveDistance(c.min_move_distance));
+        }
+        if !(c.stickiness > 0. && c.stickiness <= 1.) {
+            invalid.push(Invalid::Stickiness(c.stickiness));
+        }
+
+        if invalid.is_empty() {
+            Ok(c)
+        } else {
+            Err(Error::Config(invalid))
+        }
+    }
+}
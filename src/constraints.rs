@@ -0,0 +1,168 @@
+use crate::config::Invalid;
+use crate::structures::Vec3d;
+use crate::symmetry::{apply3, Matrix3};
+
+// Constraints limit where walkers may join the aggregate. A walker that
+// reaches a particle it isn't allowed to join to gets pushed away and
+// keeps walking, just like one that was turned down by stubbornness or
+// stickiness.
+//
+// Max_children caps how many particles may join to the same parent, and
+// must be at least 1.
+//
+// Aggregations check constraints in try_set_constraints, see Invalid.
+//
+// Min_sibling_angle, in radians, is the smallest angle allowed between
+// the directions from a parent to two of its children.
+//
+// Tips_only only lets particles join to particles that have no children
+// yet, so branches can't split.
+//
+// Sticking limits the directions walkers may join from, see Sticking.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Constraints {
+    max_children: Option<usize>,
+    min_sibling_angle: f64,
+    tips_only: bool,
+    sticking: Sticking,
+}
+
+// Sticking tells from which directions, seen from the particle they join
+// to, walkers are allowed to stick. Angles are half the opening of the
+// cone, in radians.
+//
+// Global is a cone around a fixed axis, e.g. (0, 1, 0) to only take
+// walkers coming from above in 2d, where the axis should lie in the xy
+// plane.
+//
+// Parent is a cone around the direction the particle itself grew in, from
+// its parent to it, which favours growth straight ahead. Seeds take
+// walkers from everywhere.
+//
+// The axis needs a direction and the angles have to be positive, or no
+// walker could ever stick.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Sticking {
+    #[default]
+    Anywhere,
+    Global(Vec3d, f64),
+    Parent(f64),
+}
+
+impl Constraints {
+    pub fn new() -> Constraints {
+        Constraints::default()
+    }
+
+    pub fn with_max_children(mut self, a: usize) -> Constraints {
+        self.max_children = Some(a);
+        self
+    }
+
+    pub fn with_min_sibling_angle(mut self, a: f64) -> Constraints {
+        self.min_sibling_angle = a;
+        self
+    }
+
+    pub fn with_tips_only(mut self, a: bool) -> Constraints {
+        self.tips_only = a;
+        self
+    }
+
+    pub fn with_sticking(mut self, a: Sticking) -> Constraints {
+        self.sticking = a;
+        self
+    }
+
+    pub fn max_children(&self) -> Option<usize> {
+        self.max_children
+    }
+
+    pub fn min_sibling_angle(&self) -> f64 {
+        self.min_sibling_angle
+    }
+
+    pub fn tips_only(&self) -> bool {
+        self.tips_only
+    }
+
+    pub fn sticking(&self) -> Sticking {
+        self.sticking
+    }
+
+    // The same constraints for an aggregate turned by rotation, with the
+    // axis of Sticking::Global turned along
+    pub(crate) fn rotated(&self, rotation: Matrix3) -> Constraints {
+        let mut rotated = *self;
+        if let Sticking::Global(axis, angle) = self.sticking {
+            rotated.sticking = Sticking::Global(apply3(rotation, axis), angle);
+        }
+        rotated
+    }
+
+    // The settings that would keep every walker from joining, see Invalid
+    pub(crate) fn invalid(&self) -> Vec<Invalid> {
+        let mut invalid = Vec::new();
+        if self.max_children == Some(0) {
+            invalid.push(Invalid::MaxChildren(0));
+        }
+        let angle = match self.sticking {
+            Sticking::Anywhere => None,
+            Sticking::Global(axis, angle) => {
+                let length = axis.length();
+                if !(length.is_finite() && length > 0.) {
+                    invalid.push(Invalid::StickingAxis(axis));
+                }
+                Some(angle)
+            }
+            Sticking::Parent(angle) => Some(angle),
+        };
+        if let Some(angle) = angle {
+            if angle.is_nan() || angle <= 0. {
+                invalid.push(Invalid::StickingAngle(angle));
+            }
+        }
+        invalid
+    }
+
+    // Whether a particle may be placed at p as a new child of parent, whose
+    // own parent is grandparent and which already has children. Position
+    // tells where particles are.
+    pub(crate) fn allows(
+        &self,
+        parent: usize,
+        grandparent: usize,
+        p: Vec3d,
+        children: &[usize],
+        position: impl Fn(usize) -> Vec3d,
+    ) -> bool {
+        let direction = (p - position(parent)).normalized();
+        let within = |axis: Vec3d, angle: f64| direction.dot(axis.normalized()) >= angle.cos();
+        let facing = match self.sticking {
+            Sticking::Anywhere => true,
+            Sticking::Global(axis, angle) => within(axis, angle),
+            Sticking::Parent(_) if grandparent == parent => true,
+            Sticking::Parent(angle) => within(position(parent) - position(grandparent), angle),
+        };
+        if !facing {
+            return false;
+        }
+
+        if self.tips_only && !children.is_empty() {
+            return false;
+        }
+        if let Some(max) = self.max_children {
+            if children.len() >= max {
+                return false;
+            }
+        }
+        if self.min_sibling_angle > 0. {
+            let min_cos = self.min_sibling_angle.cos();
+            return children.iter().all(|&child| {
+                let sibling = (position(child) - position(parent)).normalized();
+                direction.dot(sibling) <= min_cos
+            });
+        }
+        true
+    }
+}
@@ -0,0 +1,101 @@
+use std::{fmt, io};
+
+use crate::config::Invalid;
+
+// Error is what the try_* versions of growth, export and render return
+// where the plain ones panic.
+//
+// Empty means there is nothing to grow from yet, neither particles nor a
+// mesh to seed on.
+//
+// NoWalkers means Walkers is so sparse that not a single walker fits in
+// its domain.
+//
+// DomainFull means the aggregate covers about all of the domain of
+// Walkers, so dense walkers have nowhere left to start from.
+//
+// WalledIn means obstacles close a FlatAggregation in, away from every
+// place walkers start from.
+//
+// MasksNotRotatable means FlatAggregation::try_rotate_to_principal was
+// called with obstacles or a stickiness map set, which are laid out on the
+// pixel grid and can't be turned with the aggregate.
+//
+// SurfaceFull means a SurfaceAggregation covers about all of its surface,
+// so walkers have nowhere left to start from.
+//
+// SurfaceTooNarrow means a SurfaceAggregation's surface is narrower in
+// places than its attraction_distance, see SurfaceAggregation. It holds
+// the narrowest size.
+//
+// TooManyParticles means a CompactAggregation ran out of u32 ids.
+//
+// Config lists the parameters turned down by ConfigBuilder::build and the
+// other calls that check them, see Invalid.
+//
+// Io wraps failures to read or write files, Image failures to read images
+// in Mask::load.
+#[derive(Debug)]
+pub enum Error {
+    Empty,
+    NoWalkers,
+    DomainFull,
+    WalledIn,
+    MasksNotRotatable,
+    SurfaceFull,
+    SurfaceTooNarrow(f64),
+    TooManyParticles,
+    Config(Vec<Invalid>),
+    Io(io::Error),
+    Image(image::ImageError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Empty => write!(f, "can't find nearest, nothing to grow from"),
+            Error::NoWalkers => write!(f, "density too low for a single walker"),
+            Error::DomainFull => write!(f, "no room left for walkers in the domain"),
+            Error::WalledIn => write!(f, "obstacles wall the aggregate in"),
+            Error::MasksNotRotatable => write!(f, "can't turn obstacle or stickiness masks"),
+            Error::SurfaceFull => write!(f, "no room left on the surface"),
+            Error::SurfaceTooNarrow(size) => {
+                write!(f, "surface narrows to {}, below attraction_distance", size)
+            }
+            Error::TooManyParticles => write!(f, "too many particles for a compact aggregation"),
+            Error::Config(invalid) => {
+                write!(f, "invalid parameters")?;
+                for (i, reason) in invalid.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { "," }, reason)?;
+                }
+                Ok(())
+            }
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Image(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Image(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Error {
+        Error::Image(e)
+    }
+}
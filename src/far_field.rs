@@ -0,0 +1,131 @@
+use rand::Rng;
+
+use crate::structures::{Vec2d, Vec3d};
+
+// FarField decides what happens to walkers that wander further than twice
+// the bounding radius away from the origin.
+//
+// Reset drops the walker and launches a new one from a random point on the
+// launch sphere. That's cheap but throws away where the walker was headed
+// and biases the harmonic measure towards the side it left from.
+//
+// Return puts the walker back on the launch sphere at the point a free
+// random walk from its current position would hit first. In 3d a walk may
+// also never come back, in which case the walker is replaced with a fresh
+// one, which is exactly what a walker coming in from infinity looks like.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum FarField {
+    Reset,
+    #[default]
+    Return,
+}
+
+/// Samples the first point on the circle of `radius` around the origin hit
+/// by a random walk starting at `p` outside of it.
+///
+/// The hitting angle follows the exterior Poisson kernel, a wrapped Cauchy
+/// distribution centered on `p` with concentration `radius / |p|`.
+pub fn return_to_circle(p: Vec2d, radius: f64) -> Vec2d {
+    let mut rng = rand::thread_rng();
+    let rho = radius / p.length();
+    let u: f64 = rng.gen_range(0., 1.);
+
+    let spread = ((1. - rho) / (1. + rho)) * (std::f64::consts::PI * (u - 0.5)).tan();
+    let theta = p.angle() + 2. * spread.atan();
+
+    Vec2d::new(theta.cos(), theta.sin()) * radius
+}
+
+/// Samples the first point on the sphere of `radius` around the origin hit
+/// by a random walk starting at `p` outside of it, or `None` if the walk
+/// escapes to infinity, which happens with probability `1 - radius / |p|`.
+///
+/// Conditioned on returning, the hitting point follows the exterior Poisson
+/// kernel, whose density falls off with the cubed distance from `p` and
+/// can be inverted in closed form.
+pub fn return_to_sphere(p: Vec3d, radius: f64) -> Option<Vec3d> {
+    let mut rng = rand::thread_rng();
+    let r = p.length();
+    if rng.gen_range(0., 1.) > radius / r {
+        return None;
+    }
+
+    // invert the cdf of the cosine of the angle between p and the hit point
+    let u: f64 = rng.gen_range(0., 1.);
+    let near = 1. / (r - radius);
+    let far = 1. / (r + radius);
+    let s = (far + u * (near - far)).powi(-2);
+    let cos_theta = ((r * r + radius * radius - s) / (2. * r * radius)).clamp(-1., 1.);
+    let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+    let phi: f64 = rng.gen_range(0., 2. * std::f64::consts::PI);
+
+    let axis = p.normalized();
+    let (u, v) = orthonormal_basis(axis);
+    let direction = axis * cos_theta + (u * phi.cos() + v * phi.sin()) * sin_theta;
+
+    Some(direction * radius)
+}
+
+// Two unit vectors orthogonal to n and to each other
+fn orthonormal_basis(n: Vec3d) -> (Vec3d, Vec3d) {
+    let helper = if n.x.abs() < 0.9 {
+        Vec3d::new(1., 0., 0.)
+    } else {
+        Vec3d::new(0., 1., 0.)
+    };
+    let u = n.cross(helper).normalized();
+    (u, n.cross(u))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 20_000;
+
+    // Walks from twice the radius away come back at an angle whose cosine,
+    // measured from where they started, averages radius / |p| = 1 / 2, in
+    // 2d as in 3d
+    #[test]
+    fn circle_hits_follow_the_poisson_kernel() {
+        let p = Vec2d::new(-6., 8.);
+        let mut mean = 0.;
+        for _ in 0..SAMPLES {
+            let hit = return_to_circle(p, 5.);
+            assert!((hit.length() - 5.).abs() < 1e-9);
+            mean += (hit.angle() - p.angle()).cos() / SAMPLES as f64;
+        }
+        assert!((mean - 0.5).abs() < 0.03, "{}", mean);
+    }
+
+    #[test]
+    fn sphere_hits_follow_the_poisson_kernel() {
+        let p = Vec3d::new(1., 2., 2.);
+        let (mut returned, mut mean) = (0, 0.);
+        for _ in 0..SAMPLES {
+            if let Some(hit) = return_to_sphere(p, 1.5) {
+                assert!((hit.length() - 1.5).abs() < 1e-9);
+                returned += 1;
+                mean += hit.normalized().dot(p.normalized());
+            }
+        }
+        let share = returned as f64 / SAMPLES as f64;
+        mean /= returned as f64;
+        assert!((share - 0.5).abs() < 0.03, "{}", share);
+        assert!((mean - 0.5).abs() < 0.03, "{}", mean);
+    }
+
+    #[test]
+    fn orthonormal_basis_is_orthonormal() {
+        for n in &[
+            Vec3d::new(1., 0., 0.),
+            Vec3d::new(0.3, -0.4, 0.5).normalized(),
+        ] {
+            let (u, v) = orthonormal_basis(*n);
+            for (a, b) in &[(u, *n), (v, *n), (u, v)] {
+                assert!(a.dot(*b).abs() < 1e-12);
+            }
+            assert!((u.length() - 1.).abs() < 1e-12 && (v.length() - 1.).abs() < 1e-12);
+        }
+    }
+}
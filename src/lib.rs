@@ -11,12 +11,77 @@ const DEFAULT_MIN_MOVE_DISTANCE: f64 = 1.;
 const DEFAULT_STUBBORNNESS: usize = 0;
 const DEFAULT_STICKINESS: f64 = 1.;
 
+// Dense walkers spawned closer to the aggregate than attraction_distance
+// are drawn again, this many times at most
+const MAX_SPAWN_ATTEMPTS: usize = 100_000;
+
 pub mod structures;
-pub use structures::{vec2d, vec3d, IndexValue2d, IndexValue3d, Vec2d, Vec3d};
+pub use structures::{
+    vec2d, vec3d, IndexValue2d, IndexValue3d, Nearest, SpatialIndex, UniformGrid2d, UniformGrid3d,
+    Vec2d, Vec3d,
+};
 
 pub mod raytracer;
 pub use raytracer::render::Raycaster;
 
+pub mod far_field;
+pub use far_field::FarField;
+
+pub mod compact;
+pub use compact::CompactAggregation;
+
+pub mod colonization;
+pub use colonization::{ConvexColonization, FlatColonization};
+
+pub mod cluster;
+pub use cluster::ClusterStats;
+
+pub mod constraints;
+pub use constraints::{Constraints, Sticking};
+
+pub mod surface;
+pub use surface::{Surface, SurfaceAggregation};
+
+pub mod symmetry;
+pub use symmetry::{Symmetry2d, Symmetry3d};
+
+pub mod schedule;
+pub use schedule::{Driver, Parameter, Schedule};
+
+pub mod relax;
+pub use relax::Relaxation;
+
+pub mod sink;
+pub use sink::{BinarySink, CsvSink, JsonLinesSink, ParticleSink};
+
+pub mod validate;
+pub use validate::{Issue, Tolerances};
+
+pub mod aggregation;
+pub use aggregation::Aggregation;
+
+pub mod analysis;
+pub use analysis::{
+    BoxCounting, BoxDimension, Branch, Correlation, Fit, GyrationDimension, GyrationHistory,
+    HarmonicMeasure, MassDimension, MassRadius, Multifractal, PairCorrelation, Shape, Singularity,
+    Spectrum, Topology,
+};
+
+pub mod config;
+pub use config::{Config, ConfigBuilder, Invalid};
+
+pub mod error;
+pub use error::{Error, Result};
+
+pub mod walkers;
+pub use walkers::Walkers;
+
+pub mod mesh;
+pub use mesh::{Mesh, MeshMode};
+
+pub mod mask;
+pub use mask::Mask;
+
 // Particle_spacing defines the distance between particles that are
 // joined together
 //
@@ -35,14 +100,54 @@ pub use raytracer::render::Raycaster;
 // Bounding_radius defines the radius of the bounding sphere that bounds
 // all of the particles
 //
+// Radius is the distance of the furthest particle from the origin
+//
+// Far_field defines what happens to walkers that get too far away, see
+// FarField
+//
 // Points stores the final particle positions
 //
+// Parents stores the particle each one joined to, seeds are their own
+// parent
+//
+// Children stores the particles that joined to each particle
+//
+// Labels stores the cluster each particle belongs to, the position of its
+// seed in seeds
+//
+// Seeds stores the particles added without a parent, every one of them
+// starts a cluster
+//
+// Separate_clusters keeps clusters from growing into each other, walkers
+// won't join a cluster within attraction_distance of another one
+//
+// Constraints restricts which particles walkers may join to, see
+// Constraints
+//
+// Symmetry holds the operations of a symmetry group, other than the
+// identity, that every particle joined by add_particle is copied under,
+// see Symmetry2d and Symmetry3d
+//
+// Schedules make growth parameters change over the run, see Schedule
+//
 // Join_attempts tracks how many times other particles have attempted to
 // join with each finalized particle
 //
-// Index is the spatial index used to accelerate nearest neighbor queries
+// Index is the spatial index used to accelerate nearest neighbor queries,
+// an RTree unless another SpatialIndex is picked with Builder::*_with_index
+//
+// Sink receives every particle as it is added, see ParticleSink. The first
+// error it returns detaches it and is kept in sink_error
 pub struct Builder {}
 
+// Where a walker is after a single step. Sticking is the particle it
+// would join to, usize::MAX for a new seed, and where it would be placed.
+enum Step<V> {
+    Walking(V),
+    Sticking(usize, V),
+    Joined,
+}
+
 impl Builder {
     pub fn flat() -> FlatAggregation {
         FlatAggregation::new()
@@ -51,23 +156,78 @@ impl Builder {
     pub fn convex() -> ConvexAggregation {
         ConvexAggregation::new()
     }
+
+    pub fn compact() -> CompactAggregation {
+        CompactAggregation::new()
+    }
+
+    pub fn surface(surface: Surface) -> SurfaceAggregation {
+        SurfaceAggregation::new(surface)
+    }
+
+    pub fn flat_colonization() -> FlatColonization {
+        FlatColonization::new()
+    }
+
+    pub fn convex_colonization() -> ConvexColonization {
+        ConvexColonization::new()
+    }
+
+    pub fn flat_with_index<I: SpatialIndex<Vec2d>>(index: I) -> FlatAggregation<I> {
+        FlatAggregation::with_index(index)
+    }
+
+    pub fn convex_with_index<I: SpatialIndex<Vec3d>>(index: I) -> ConvexAggregation<I> {
+        ConvexAggregation::with_index(index)
+    }
 }
 
+// Aggregations can be grown on another thread, sinks and schedules have to
+// be Send to keep it that way
+const _: fn() = || {
+    fn send<T: Send>() {}
+    send::<FlatAggregation>();
+    send::<ConvexAggregation>();
+    send::<CompactAggregation>();
+    send::<SurfaceAggregation>();
+    send::<FlatColonization>();
+    send::<ConvexColonization>();
+};
+
 #[derive(Default)]
-pub struct ConvexAggregation {
+pub struct ConvexAggregation<I = RTree<IndexValue3d>> {
     particle_spacing: f64,
     attraction_distance: f64,
     min_move_distanse: f64,
     stubbornness: usize,
     stickiness: f64,
     bounding_radius: f64,
+    radius: f64,
+    far_field: FarField,
     join_attempts: Vec<usize>,
     pub points: Vec<Vec3d>,
-    pub index: RTree<IndexValue3d>,
+    pub parents: Vec<usize>,
+    children: Vec<Vec<usize>>,
+    pub labels: Vec<usize>,
+    seeds: Vec<usize>,
+    separate_clusters: bool,
+    constraints: Constraints,
+    schedules: Vec<(Parameter, Schedule)>,
+    symmetry: Vec<symmetry::Matrix3>,
+    mesh: Option<mesh::MeshSurface>,
+    pub index: I,
+    sink: Option<Box<dyn ParticleSink>>,
+    sink_error: Option<std::io::Error>,
 }
 
 impl ConvexAggregation {
     pub fn new() -> ConvexAggregation {
+        ConvexAggregation::with_index(RTree::new())
+    }
+}
+
+impl<I: SpatialIndex<Vec3d>> ConvexAggregation<I> {
+    pub fn with_index(index: I) -> ConvexAggregation<I> {
         ConvexAggregation {
             particle_spacing: DEFAULT_PARTICLE_SPACING,
             attraction_distance: DEFAULT_ATTRACTION_DISTANCE,
@@ -75,9 +235,22 @@ impl ConvexAggregation {
             stubbornness: DEFAULT_STUBBORNNESS,
             stickiness: DEFAULT_STICKINESS,
             bounding_radius: 0.,
+            radius: 0.,
+            far_field: FarField::default(),
             points: Vec::new(),
+            parents: Vec::new(),
+            children: Vec::new(),
+            labels: Vec::new(),
+            seeds: Vec::new(),
+            separate_clusters: false,
+            constraints: Constraints::default(),
+            schedules: Vec::new(),
+            symmetry: Vec::new(),
+            mesh: None,
             join_attempts: Vec::new(),
-            index: RTree::new(),
+            index,
+            sink: None,
+            sink_error: None,
         }
     }
 
@@ -101,6 +274,95 @@ impl ConvexAggregation {
         self.stickiness = a;
     }
 
+    // Sets every growth parameter at once from a checked Config
+    pub fn set_config(&mut self, a: Config) {
+        self.set_particle_spacing(a.particle_spacing());
+        self.set_attraction_distance(a.attraction_distance());
+        self.set_min_move_distance(a.min_move_distance());
+        self.set_stubbornness(a.stubbornness());
+        self.set_stickness(a.stickiness());
+    }
+
+    pub fn set_far_field(&mut self, a: FarField) {
+        self.far_field = a;
+    }
+
+    pub fn set_separate_clusters(&mut self, a: bool) {
+        self.separate_clusters = a;
+    }
+
+    pub fn set_constraints(&mut self, a: Constraints) {
+        if let Err(e) = self.try_set_constraints(a) {
+            panic!("{}", e);
+        }
+    }
+
+    // Like set_constraints, but returns an Error instead of panicking when
+    // no walker could join under a
+    pub fn try_set_constraints(&mut self, a: Constraints) -> Result<()> {
+        let invalid = a.invalid();
+        if !invalid.is_empty() {
+            return Err(Error::Config(invalid));
+        }
+        self.constraints = a;
+        Ok(())
+    }
+
+    // Replaces the schedule parameter follows, if it had one
+    pub fn set_schedule(&mut self, parameter: Parameter, schedule: Schedule) {
+        self.clear_schedule(parameter);
+        self.schedules.push((parameter, schedule));
+    }
+
+    pub fn clear_schedule(&mut self, parameter: Parameter) {
+        self.schedules.retain(|(p, _)| *p != parameter);
+    }
+
+    fn apply_schedules(&mut self) {
+        for (parameter, schedule) in &self.schedules {
+            let a = schedule.value(self.points.len(), self.radius);
+            match parameter {
+                Parameter::ParticleSpacing => self.particle_spacing = a,
+                Parameter::AttractionDistance => self.attraction_distance = a,
+                Parameter::MinMoveDistance => self.min_move_distanse = a,
+                Parameter::Stubbornness => self.stubbornness = a.max(0.).round() as usize,
+                Parameter::Stickiness => self.stickiness = a,
+            }
+        }
+    }
+
+    pub fn set_symmetry(&mut self, a: Symmetry3d) {
+        self.symmetry = a.operations();
+    }
+
+    // Grows around the surface of mesh, see MeshMode. The mesh stays where
+    // it is, Mesh::fit moves it to the origin. Walkers launch around it, so
+    // in Seed mode the aggregate doesn't need any particles to start from.
+    pub fn set_mesh(&mut self, mesh: &Mesh, mode: MeshMode) {
+        let surface = mesh::MeshSurface::new(mesh, mode);
+        self.bounding_radius = self
+            .bounding_radius
+            .max(surface.radius + self.attraction_distance);
+        self.mesh = Some(surface);
+    }
+
+    pub fn clear_mesh(&mut self) {
+        self.mesh = None;
+    }
+
+    pub fn set_sink<S: ParticleSink + Send + 'static>(&mut self, a: S) {
+        self.sink = Some(Box::new(a));
+        self.sink_error = None;
+    }
+
+    pub fn take_sink(&mut self) -> Option<Box<dyn ParticleSink>> {
+        self.sink.take()
+    }
+
+    pub fn sink_error(&self) -> Option<&std::io::Error> {
+        self.sink_error.as_ref()
+    }
+
     pub fn random_starting_position(&self) -> Vec3d {
         let d = self.bounding_radius;
         vec3d::Vec3d::random().normalized() * d
@@ -110,6 +372,14 @@ impl ConvexAggregation {
         p.length() > self.bounding_radius * 2.
     }
 
+    fn return_to_launch_sphere(&self, p: Vec3d) -> Vec3d {
+        match self.far_field {
+            FarField::Reset => self.random_starting_position(),
+            FarField::Return => far_field::return_to_sphere(p, self.bounding_radius)
+                .unwrap_or_else(|| self.random_starting_position()),
+        }
+    }
+
     fn should_join(&mut self, parent: usize) -> bool {
         self.join_attempts[parent] += 1;
         if self.join_attempts[parent] < self.stubbornness {
@@ -118,6 +388,41 @@ impl ConvexAggregation {
         rand::thread_rng().gen_range(0., 1.) <= self.stickiness
     }
 
+    fn can_join(&self, p: Vec3d, parent: usize) -> bool {
+        let points = &self.points;
+        let position = |i: usize| points[i];
+        if !self.constraints.allows(
+            parent,
+            self.parents[parent],
+            p,
+            &self.children[parent],
+            position,
+        ) {
+            return false;
+        }
+
+        // walkers can get well within attraction_distance before they are
+        // caught, keep their particles clear of the others, see Tolerances
+        let clearance = self.particle_spacing / 2.;
+        if self
+            .index
+            .within(&p, clearance)
+            .iter()
+            .any(|&i| i != parent)
+        {
+            return false;
+        }
+
+        if self.separate_clusters {
+            let label = self.labels[parent];
+            let others = self.index.within(&p, self.attraction_distance);
+            if others.iter().any(|&i| self.labels[i] != label) {
+                return false;
+            }
+        }
+        true
+    }
+
     fn place_particle(&self, p: Vec3d, parent: usize) -> Vec3d {
         vec3d::lerp(self.points[parent], p, self.particle_spacing)
     }
@@ -126,60 +431,390 @@ impl ConvexAggregation {
         vec3d::Vec3d::random()
     }
 
-    pub fn add(&mut self, p: Vec3d, _parent: usize) {
+    // Adds p as a seed of its own. Growing from several seeds needs them
+    // added this way, add(p, 0) joins p to the first particle once there is
+    // one.
+    pub fn add_seed(&mut self, p: Vec3d) {
+        self.add(p, usize::MAX);
+    }
+
+    // A parent that isn't an already added particle makes p a seed
+    pub fn add(&mut self, p: Vec3d, parent: usize) {
         let id = self.points.len();
-        self.index.insert(IndexValue3d { vec: p, index: id });
+        let parent = if parent < id { parent } else { id };
+        self.index.insert(p, id);
         self.points.push(p);
+        self.parents.push(parent);
+        self.children.push(Vec::new());
+        if parent != id {
+            self.children[parent].push(id);
+            self.labels.push(self.labels[parent]);
+        } else {
+            self.labels.push(self.seeds.len());
+            self.seeds.push(id);
+        }
         self.join_attempts.push(0);
+        sink::emit(
+            &mut self.sink,
+            &mut self.sink_error,
+            id,
+            parent,
+            [p.x, p.y, p.z],
+        );
+        self.radius = self.radius.max(p.length());
         self.bounding_radius = self
             .bounding_radius
             .max(p.length() + self.attraction_distance);
     }
 
-    fn nearest(&mut self, p: &Vec3d) -> usize {
-        let nearest = self
-            .index
-            .nearest_neighbor(&IndexValue3d { vec: *p, index: 0 })
-            .expect("can't find nearest");
-        nearest.index
+    // Adds the copies of p, that joined to parent, under the symmetry
+    // group. Each copy joins to the copy of parent under the same
+    // operation, and is left out when that copy of parent was left out
+    // itself, when it would land on an existing particle, like the ones of
+    // particles on a mirror, or when the constraints don't allow it there.
+    // Stickiness and stubbornness were already settled by p.
+    fn add_symmetric_copies(&mut self, p: Vec3d, parent: usize) {
+        let parent_position = self.points[parent];
+        let overlap = self.particle_spacing / 2.;
+        // copies of the same particle under the same operation only differ
+        // by rounding
+        let exact = self.particle_spacing * 1e-6;
+
+        for m in self.symmetry.clone() {
+            let copy = symmetry::apply3(m, p);
+            if let Nearest::Particle(_, d) = self.index.nearest(&copy, self.search_radius()) {
+                if d < overlap {
+                    continue;
+                }
+            }
+
+            let parent_copy = symmetry::apply3(m, parent_position);
+            let parent = match self.index.nearest(&parent_copy, self.search_radius()) {
+                Nearest::Particle(i, d) if d < exact => i,
+                _ => continue,
+            };
+            if !self.can_join(copy, parent) {
+                continue;
+            }
+            self.add(copy, parent);
+        }
     }
 
-    pub fn add_particle(&mut self) {
-        let mut p = self.random_starting_position();
+    // Smooths the aggregate in place, see Relaxation. Returns the number of
+    // passes made.
+    pub fn relax(&mut self, relaxation: Relaxation) -> usize {
+        let mut points: Vec<_> = self.points.iter().map(|p| p.as_slice()).collect();
+        let passes = relaxation.run(&mut points, &self.parents, self.particle_spacing);
+        self.points = points.into_iter().map(Vec3d::from).collect();
+
+        self.index.clear();
+        self.radius = 0.;
+        for (id, p) in self.points.iter().enumerate() {
+            self.index.insert(*p, id);
+            self.radius = self.radius.max(p.length());
+        }
+        let mesh_radius = self.mesh.as_ref().map_or(0., |mesh| mesh.radius);
+        self.bounding_radius = self.radius.max(mesh_radius) + self.attraction_distance;
+        passes
+    }
+
+    // Turns the aggregate about the origin so its principal axes line up
+    // with x, y and z, the longest along x, see Shape. The mesh, the
+    // symmetry group and the axis of Sticking::Global are turned with it,
+    // so growth carries on as if it had started out that way. Returns the
+    // rotation.
+    pub fn rotate_to_principal(&mut self) -> symmetry::Matrix3 {
+        let rotation = Shape::new(self).rotation();
+        for p in self.points.iter_mut() {
+            *p = symmetry::apply3(rotation, *p);
+        }
+        self.mesh = self.mesh.as_ref().map(|mesh| mesh.rotated(rotation));
+        for m in self.symmetry.iter_mut() {
+            *m = symmetry::conjugate3(rotation, *m);
+        }
+        self.constraints = self.constraints.rotated(rotation);
+
+        self.index.clear();
+        for (id, p) in self.points.iter().enumerate() {
+            self.index.insert(*p, id);
+        }
+        rotation
+    }
+
+    // Checks the aggregate for broken invariants with the default
+    // Tolerances, see Issue
+    pub fn validate(&self) -> Vec<Issue> {
+        self.validate_with(Tolerances::default())
+    }
+
+    pub fn validate_with(&self, tolerances: Tolerances) -> Vec<Issue> {
+        let points: Vec<_> = self.points.iter().map(|p| p.as_slice()).collect();
+        let mut issues = tolerances.check(&points, &self.parents, self.particle_spacing);
+
+        if self.index.size() != self.points.len() {
+            issues.push(Issue::IndexSize(self.index.size()));
+        }
+        for (i, p) in self.points.iter().enumerate() {
+            let finite = p.as_slice().iter().all(|x| x.is_finite());
+            if finite && !self.index.within(p, 1e-9).contains(&i) {
+                issues.push(Issue::NotIndexed(i));
+            }
+        }
+        issues
+    }
+
+    pub fn seeds(&self) -> &[usize] {
+        &self.seeds
+    }
+
+    // One entry per cluster, in the order of seeds
+    pub fn cluster_stats(&self) -> Vec<ClusterStats<Vec3d>> {
+        let mut stats: Vec<_> = self
+            .seeds
+            .iter()
+            .map(|&seed| ClusterStats {
+                seed,
+                particles: 0,
+                center: Vec3d::zero(),
+                radius_of_gyration: 0.,
+                extent: 0.,
+            })
+            .collect();
+        let mut squares = vec![0.; stats.len()];
 
-        loop {
-            let parent = self.nearest(&p);
+        for (p, &label) in self.points.iter().zip(&self.labels) {
+            let cluster = &mut stats[label];
+            cluster.particles += 1;
+            cluster.center += *p;
+            cluster.extent = cluster.extent.max(p.distance(self.points[cluster.seed]));
+            squares[label] += p.length_squared();
+        }
+
+        for (cluster, square) in stats.iter_mut().zip(squares) {
+            let n = cluster.particles as f64;
+            cluster.center = cluster.center / n;
+            cluster.radius_of_gyration = (square / n - cluster.center.length_squared())
+                .max(0.)
+                .sqrt();
+        }
+        stats
+    }
+
+    // Whether walkers can start new seeds on a mesh, so growth needs no
+    // particles to start from. An obstacle mesh alone has nothing to join.
+    fn seeds_on_mesh(&self) -> bool {
+        self.mesh.as_ref().map(|mesh| mesh.mode) == Some(MeshMode::Seed)
+    }
+
+    // Walkers closer than this to a particle always get an exact answer from
+    // the index, further away it may only tell how much room there is
+    fn search_radius(&self) -> f64 {
+        self.attraction_distance * 2.
+    }
+
+    // Moves the walker at p once, or adds it to the aggregate if it is
+    // close enough to join
+    fn step(&mut self, p: Vec3d) -> Step<Vec3d> {
+        match self.walk(p) {
+            Step::Sticking(parent, placed) => {
+                // add the point
+                self.add(placed, parent);
+                let parent = self.parents[self.points.len() - 1];
+                self.add_symmetric_copies(placed, parent);
+                Step::Joined
+            }
+            step => step,
+        }
+    }
 
-            let d = p.distance(self.points[parent]);
+    // Like step, but leaves walkers that would join as Sticking
+    fn walk(&mut self, mut p: Vec3d) -> Step<Vec3d> {
+        let d = match self.index.nearest(&p, self.search_radius()) {
+            Nearest::Particle(parent, d) if d < self.attraction_distance => {
+                // adjust particle position in relation to its parent
+                let placed = self.place_particle(p, parent);
 
-            if d < self.attraction_distance {
-                if !self.should_join(parent) {
+                if !self.can_join(placed, parent) || !self.should_join(parent) {
                     // push particle away a bit
-                    p = vec3d::lerp(
+                    return Step::Walking(vec3d::lerp(
                         self.points[parent],
                         p,
                         self.attraction_distance + self.min_move_distanse,
-                    );
-                    continue;
+                    ));
                 }
 
-                // adjust particle position in relation to its parent
-                p = self.place_particle(p, parent);
+                return Step::Sticking(parent, placed);
+            }
+            Nearest::Particle(_, d) | Nearest::Clear(d) => d,
+        };
 
-                // add the point
-                self.add(p, parent);
-                return;
+        let d = match self.mesh.as_ref().and_then(|mesh| mesh.closest(p)) {
+            Some((closest, m)) if m < self.attraction_distance => {
+                return self.touch_mesh(p, closest, m);
             }
+            Some((_, m)) => d.min(m),
+            None => d,
+        };
+
+        // move randomly
+        let m = self.min_move_distanse.max(d - self.attraction_distance);
+        p += self.motion_vector().normalized() * m;
+        Step::Walking(p)
+    }
+
+    // The walker at p came within distance of the mesh at closest. Sticks it
+    // to the surface as a new seed, or pushes it away from an obstacle or
+    // when it would land on a particle.
+    fn touch_mesh(&mut self, p: Vec3d, closest: Vec3d, distance: f64) -> Step<Vec3d> {
+        let outwards = if distance > 0. {
+            (p - closest) / distance
+        } else {
+            Vec3d::random().normalized()
+        };
+
+        if self.seeds_on_mesh() {
+            let placed = closest + outwards * (self.particle_spacing / 2.);
+            let crowded = match self.index.nearest(&placed, self.search_radius()) {
+                Nearest::Particle(_, d) => d < self.particle_spacing,
+                Nearest::Clear(_) => false,
+            };
+            if !crowded && rand::thread_rng().gen_range(0., 1.) <= self.stickiness {
+                return Step::Sticking(usize::MAX, placed);
+            }
+        }
+
+        // push particle away a bit
+        Step::Walking(closest + outwards * (self.attraction_distance + self.min_move_distanse))
+    }
 
-            // move randomly
-            let m = self.min_move_distanse.max(d - self.attraction_distance);
-            p += self.motion_vector().normalized() * m;
+    pub fn add_particle(&mut self) {
+        if let Err(e) = self.try_add_particle() {
+            panic!("{}", e);
+        }
+    }
 
-            // check if particle is too far away, reset if so
+    // Like add_particle, but returns an Error instead of panicking when
+    // there is nothing to grow from
+    pub fn try_add_particle(&mut self) -> Result<()> {
+        if self.points.is_empty() && !self.seeds_on_mesh() {
+            return Err(Error::Empty);
+        }
+
+        self.apply_schedules();
+
+        let mut p = self.random_starting_position();
+
+        while let Step::Walking(next) = self.step(p) {
+            p = next;
+
+            // check if particle is too far away, bring it back if so
             if self.should_reset(p) {
-                p = self.random_starting_position();
+                p = self.return_to_launch_sphere(p);
+            }
+        }
+        Ok(())
+    }
+
+    // Launches as many test walkers as walkers one after another, which move
+    // and stick the way new particles would, and counts where they stick
+    // instead of adding them, see HarmonicMeasure. The aggregate is left as
+    // it was.
+    pub fn probe(&mut self, walkers: usize) -> HarmonicMeasure {
+        let mut hits = vec![0; self.points.len()];
+        let mut missed = 0;
+        if self.points.is_empty() && !self.seeds_on_mesh() {
+            return HarmonicMeasure::new(self, hits, missed);
+        }
+
+        // every test walker meets stubbornness as the aggregate is now, not
+        // worn down by the ones before it
+        let join_attempts = self.join_attempts.clone();
+        for _ in 0..walkers {
+            if self.stubbornness > 0 {
+                self.join_attempts.copy_from_slice(&join_attempts);
+            }
+
+            let mut p = self.random_starting_position();
+            let parent = loop {
+                match self.walk(p) {
+                    Step::Walking(next) => p = next,
+                    Step::Sticking(parent, _) => break parent,
+                    Step::Joined => unreachable!("walk never adds particles"),
+                }
+
+                // check if particle is too far away, bring it back if so
+                if self.should_reset(p) {
+                    p = self.return_to_launch_sphere(p);
+                }
+            };
+            match hits.get_mut(parent) {
+                Some(count) => *count += 1,
+                None => missed += 1,
+            }
+        }
+        self.join_attempts = join_attempts;
+
+        HarmonicMeasure::new(self, hits, missed)
+    }
+
+    // A walker at a random place in the domain of walkers, away from the
+    // aggregate
+    fn spawn_walker(&self, walkers: &Walkers) -> Result<Vec3d> {
+        for _ in 0..MAX_SPAWN_ATTEMPTS {
+            let p = Vec3d::random() * walkers.radius();
+            match self.index.nearest(&p, self.search_radius()) {
+                Nearest::Particle(_, d) if d < self.attraction_distance => continue,
+                _ => return Ok(p),
             }
         }
+        Err(Error::DomainFull)
+    }
+
+    // Grows the aggregate by at least particles with finite density of
+    // walkers, see Walkers. Stops early when the aggregate reaches the edge
+    // of the domain. Returns the number of particles added, or panics when
+    // the aggregate fills the domain so walkers can't be spawned away from
+    // it.
+    pub fn grow_dense(&mut self, walkers: Walkers, particles: usize) -> usize {
+        self.try_grow_dense(walkers, particles)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like grow_dense, but returns an Error instead of panicking. Particles
+    // added before DomainFull stay in the aggregate.
+    pub fn try_grow_dense(&mut self, walkers: Walkers, particles: usize) -> Result<usize> {
+        if self.points.is_empty() && !self.seeds_on_mesh() {
+            return Err(Error::Empty);
+        }
+        if walkers.count3d() == 0 {
+            return Err(Error::NoWalkers);
+        }
+
+        let start = self.points.len();
+        let mut crowd: Vec<(Vec3d, usize)> = (0..walkers.count3d())
+            .map(|_| Ok((self.spawn_walker(&walkers)?, 0)))
+            .collect::<Result<_>>()?;
+
+        while self.points.len() - start < particles && self.radius < walkers.radius() {
+            self.apply_schedules();
+
+            for walker in crowd.iter_mut() {
+                let (p, age) = *walker;
+                *walker = match self.step(p) {
+                    Step::Walking(next)
+                        if next.length() <= walkers.radius() && !walkers.expires(age + 1) =>
+                    {
+                        (next, age + 1)
+                    }
+                    // joined, left or expired, replace it
+                    _ => (self.spawn_walker(&walkers)?, 0),
+                };
+                if self.points.len() - start >= particles {
+                    break;
+                }
+            }
+        }
+        Ok(self.points.len() - start)
     }
 
     pub fn save_csv(&self, name: &str) -> std::io::Result<()> {
@@ -190,15 +825,12 @@ impl ConvexAggregation {
         let mut file = File::create(name)?;
         file.write_all(b"index,parent,x,y,z\n")?;
 
-        for (index, val) in self.index.iter().enumerate() {
-            file.write_all(
-                &format!(
-                    "{},{},{:.04},{:.04},{:.04}\n",
-                    index, val.index, val.vec.x, val.vec.y, val.vec.z
-                )
-                .as_bytes(),
-            )
-            .expect("second write");
+        for (index, (val, parent)) in self.points.iter().zip(&self.parents).enumerate() {
+            writeln!(
+                file,
+                "{},{},{:.04},{:.04},{:.04}",
+                index, parent, val.x, val.y, val.z
+            )?;
         }
 
         Ok(())
@@ -211,21 +843,89 @@ impl ConvexAggregation {
     // }
 }
 
+impl<I: SpatialIndex<Vec3d>> Aggregation for ConvexAggregation<I> {
+    type Vector = Vec3d;
+
+    fn add(&mut self, p: Vec3d, parent: usize) {
+        self.add(p, parent)
+    }
+
+    fn add_particle(&mut self) {
+        self.add_particle()
+    }
+
+    fn try_add_particle(&mut self) -> Result<()> {
+        self.try_add_particle()
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn dimensions(&self) -> usize {
+        3
+    }
+
+    fn position(&self, index: usize) -> Vec3d {
+        self.points[index]
+    }
+
+    fn parent(&self, index: usize) -> usize {
+        self.parents[index]
+    }
+
+    fn within(&self, p: Vec3d, radius: f64) -> Vec<usize> {
+        self.index.within(&p, radius)
+    }
+
+    fn particle_spacing(&self) -> f64 {
+        self.particle_spacing
+    }
+
+    fn attraction_distance(&self) -> f64 {
+        self.attraction_distance
+    }
+
+    fn bounding_radius(&self) -> f64 {
+        self.bounding_radius
+    }
+}
+
 #[derive(Default)]
-pub struct FlatAggregation {
+pub struct FlatAggregation<I = RTree<IndexValue2d>> {
     particle_spacing: f64,
     attraction_distance: f64,
     min_move_distanse: f64,
     stubbornness: usize,
     stickiness: f64,
     bounding_radius: f64,
+    radius: f64,
+    far_field: FarField,
     join_attempts: Vec<usize>,
     pub points: Vec<Vec2d>,
-    pub index: RTree<IndexValue2d>,
+    pub parents: Vec<usize>,
+    children: Vec<Vec<usize>>,
+    pub labels: Vec<usize>,
+    seeds: Vec<usize>,
+    separate_clusters: bool,
+    constraints: Constraints,
+    schedules: Vec<(Parameter, Schedule)>,
+    symmetry: Vec<symmetry::Matrix2>,
+    obstacles: Option<mask::Obstacles>,
+    stickiness_map: Option<Mask>,
+    pub index: I,
+    sink: Option<Box<dyn ParticleSink>>,
+    sink_error: Option<std::io::Error>,
 }
 
 impl FlatAggregation {
     pub fn new() -> FlatAggregation {
+        FlatAggregation::with_index(RTree::new())
+    }
+}
+
+impl<I: SpatialIndex<Vec2d>> FlatAggregation<I> {
+    pub fn with_index(index: I) -> FlatAggregation<I> {
         FlatAggregation {
             particle_spacing: DEFAULT_PARTICLE_SPACING,
             attraction_distance: DEFAULT_ATTRACTION_DISTANCE,
@@ -233,9 +933,23 @@ impl FlatAggregation {
             stubbornness: DEFAULT_STUBBORNNESS,
             stickiness: DEFAULT_STICKINESS,
             bounding_radius: 0.,
+            radius: 0.,
+            far_field: FarField::default(),
             points: Vec::new(),
+            parents: Vec::new(),
+            children: Vec::new(),
+            labels: Vec::new(),
+            seeds: Vec::new(),
+            separate_clusters: false,
+            constraints: Constraints::default(),
+            schedules: Vec::new(),
+            symmetry: Vec::new(),
+            obstacles: None,
+            stickiness_map: None,
             join_attempts: Vec::new(),
-            index: RTree::new(),
+            index,
+            sink: None,
+            sink_error: None,
         }
     }
 
@@ -259,21 +973,207 @@ impl FlatAggregation {
         self.stickiness = a;
     }
 
+    // Sets every growth parameter at once from a checked Config
+    pub fn set_config(&mut self, a: Config) {
+        self.set_particle_spacing(a.particle_spacing());
+        self.set_attraction_distance(a.attraction_distance());
+        self.set_min_move_distance(a.min_move_distance());
+        self.set_stubbornness(a.stubbornness());
+        self.set_stickness(a.stickiness());
+    }
+
+    pub fn set_far_field(&mut self, a: FarField) {
+        self.far_field = a;
+    }
+
+    pub fn set_separate_clusters(&mut self, a: bool) {
+        self.separate_clusters = a;
+    }
+
+    pub fn set_constraints(&mut self, a: Constraints) {
+        if let Err(e) = self.try_set_constraints(a) {
+            panic!("{}", e);
+        }
+    }
+
+    // Like set_constraints, but returns an Error instead of panicking when
+    // no walker could join under a
+    pub fn try_set_constraints(&mut self, a: Constraints) -> Result<()> {
+        let invalid = a.invalid();
+        if !invalid.is_empty() {
+            return Err(Error::Config(invalid));
+        }
+        self.constraints = a;
+        Ok(())
+    }
+
+    // Replaces the schedule parameter follows, if it had one
+    pub fn set_schedule(&mut self, parameter: Parameter, schedule: Schedule) {
+        self.clear_schedule(parameter);
+        self.schedules.push((parameter, schedule));
+    }
+
+    pub fn clear_schedule(&mut self, parameter: Parameter) {
+        self.schedules.retain(|(p, _)| *p != parameter);
+    }
+
+    fn apply_schedules(&mut self) {
+        for (parameter, schedule) in &self.schedules {
+            let a = schedule.value(self.points.len(), self.radius);
+            match parameter {
+                Parameter::ParticleSpacing => self.particle_spacing = a,
+                Parameter::AttractionDistance => self.attraction_distance = a,
+                Parameter::MinMoveDistance => self.min_move_distanse = a,
+                Parameter::Stubbornness => self.stubbornness = a.max(0.).round() as usize,
+                Parameter::Stickiness => self.stickiness = a,
+            }
+        }
+    }
+
+    pub fn set_symmetry(&mut self, a: Symmetry2d) {
+        self.symmetry = a.operations();
+    }
+
+    // Adds a seed at the center of every pixel of mask brighter than
+    // threshold, leaving out the ones closer than particle_spacing to a
+    // particle. Returns the number of seeds added.
+    pub fn add_seeds(&mut self, mask: &Mask, threshold: f64) -> usize {
+        let start = self.points.len();
+        for p in mask.positions_over(threshold) {
+            if let Nearest::Particle(_, d) = self.index.nearest(&p, self.search_radius()) {
+                if d < self.particle_spacing {
+                    continue;
+                }
+            }
+            self.add_seed(p);
+        }
+        self.points.len() - start
+    }
+
+    // Walkers can't enter pixels of mask brighter than threshold, and
+    // particles aren't placed in them
+    pub fn set_obstacles(&mut self, mask: Mask, threshold: f64) {
+        let mut obstacles = mask::Obstacles::new(mask, threshold);
+        for p in &self.points {
+            obstacles.reach(*p);
+        }
+        self.obstacles = Some(obstacles);
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.obstacles = None;
+    }
+
+    // Scales stickiness by the value of mask where a particle would be
+    // placed, outside the mask stickiness is left as it is
+    pub fn set_stickiness_map(&mut self, mask: Mask) {
+        self.stickiness_map = Some(mask);
+    }
+
+    pub fn clear_stickiness_map(&mut self) {
+        self.stickiness_map = None;
+    }
+
+    pub fn set_sink<S: ParticleSink + Send + 'static>(&mut self, a: S) {
+        self.sink = Some(Box::new(a));
+        self.sink_error = None;
+    }
+
+    pub fn take_sink(&mut self) -> Option<Box<dyn ParticleSink>> {
+        self.sink.take()
+    }
+
+    pub fn sink_error(&self) -> Option<&std::io::Error> {
+        self.sink_error.as_ref()
+    }
+
     pub fn random_starting_position(&self) -> Vec2d {
         let d = self.bounding_radius;
         vec2d::random_in_unit_sphere().normalized() * d
     }
 
+    // A starting position walkers can get to the aggregate from, obstacles
+    // may wall parts of the launch circle off from it. None when they wall
+    // in all of it.
+    fn launch_position(&self) -> Option<Vec2d> {
+        (0..MAX_SPAWN_ATTEMPTS)
+            .map(|_| self.random_starting_position())
+            .find(|&p| !self.sealed_off(p))
+    }
+
+    fn sealed_off(&self, p: Vec2d) -> bool {
+        self.obstacles.as_ref().is_some_and(|o| o.sealed_off(p))
+    }
+
     fn should_reset(&self, p: Vec2d) -> bool {
         p.length() > self.bounding_radius * 2.
     }
 
-    fn should_join(&mut self, parent: usize) -> bool {
+    fn return_to_launch_sphere(&self, p: Vec2d) -> Vec2d {
+        match self.far_field {
+            FarField::Reset => self.launch_position().unwrap_or(p),
+            FarField::Return => {
+                let back = far_field::return_to_circle(p, self.bounding_radius);
+                if self.sealed_off(back) {
+                    self.launch_position().unwrap_or(p)
+                } else {
+                    back
+                }
+            }
+        }
+    }
+
+    fn should_join(&mut self, parent: usize, p: Vec2d) -> bool {
         self.join_attempts[parent] += 1;
         if self.join_attempts[parent] < self.stubbornness {
             return false;
         }
-        rand::thread_rng().gen_range(0., 1.) <= self.stickiness
+        let scale = self
+            .stickiness_map
+            .as_ref()
+            .and_then(|map| map.value(p))
+            .unwrap_or(1.);
+        rand::thread_rng().gen_range(0., 1.) <= self.stickiness * scale
+    }
+
+    fn can_join(&self, p: Vec2d, parent: usize) -> bool {
+        // neither in an obstacle nor across one from the parent
+        if self.crosses_obstacle(self.points[parent], p) {
+            return false;
+        }
+
+        let points = &self.points;
+        let position = |i: usize| Vec3d::from(points[i]);
+        if !self.constraints.allows(
+            parent,
+            self.parents[parent],
+            p.into(),
+            &self.children[parent],
+            position,
+        ) {
+            return false;
+        }
+
+        // walkers can get well within attraction_distance before they are
+        // caught, keep their particles clear of the others, see Tolerances
+        let clearance = self.particle_spacing / 2.;
+        if self
+            .index
+            .within(&p, clearance)
+            .iter()
+            .any(|&i| i != parent)
+        {
+            return false;
+        }
+
+        if self.separate_clusters {
+            let label = self.labels[parent];
+            let others = self.index.within(&p, self.attraction_distance);
+            if others.iter().any(|&i| self.labels[i] != label) {
+                return false;
+            }
+        }
+        true
     }
 
     fn place_particle(&self, p: Vec2d, parent: usize) -> Vec2d {
@@ -284,60 +1184,397 @@ impl FlatAggregation {
         vec2d::random_in_unit_sphere()
     }
 
-    pub fn add(&mut self, p: Vec2d, _parent: usize) {
+    // Adds p as a seed of its own. Growing from several seeds needs them
+    // added this way, add(p, 0) joins p to the first particle once there is
+    // one.
+    pub fn add_seed(&mut self, p: Vec2d) {
+        self.add(p, usize::MAX);
+    }
+
+    // A parent that isn't an already added particle makes p a seed
+    pub fn add(&mut self, p: Vec2d, parent: usize) {
         let id = self.points.len();
-        self.index.insert(IndexValue2d { vec: p, index: id });
+        let parent = if parent < id { parent } else { id };
+        self.index.insert(p, id);
         self.points.push(p);
+        self.parents.push(parent);
+        self.children.push(Vec::new());
+        if parent != id {
+            self.children[parent].push(id);
+            self.labels.push(self.labels[parent]);
+        } else {
+            self.labels.push(self.seeds.len());
+            self.seeds.push(id);
+        }
         self.join_attempts.push(0);
+        sink::emit(
+            &mut self.sink,
+            &mut self.sink_error,
+            id,
+            parent,
+            [p.x, p.y, 0.],
+        );
+        if let Some(obstacles) = &mut self.obstacles {
+            obstacles.reach(p);
+        }
+        self.radius = self.radius.max(p.length());
         self.bounding_radius = self
             .bounding_radius
             .max(p.length() + self.attraction_distance);
     }
 
-    fn nearest(&mut self, p: &Vec2d) -> usize {
-        let nearest = self
-            .index
-            .nearest_neighbor(&IndexValue2d { vec: *p, index: 0 })
-            .expect("can't find nearest");
-        nearest.index
+    // Adds the copies of p, that joined to parent, under the symmetry
+    // group. Each copy joins to the copy of parent under the same
+    // operation, and is left out when that copy of parent was left out
+    // itself, when it would land on an existing particle, like the ones of
+    // particles on a mirror, or when the constraints don't allow it there.
+    // Stickiness and stubbornness were already settled by p.
+    fn add_symmetric_copies(&mut self, p: Vec2d, parent: usize) {
+        let parent_position = self.points[parent];
+        let overlap = self.particle_spacing / 2.;
+        // copies of the same particle under the same operation only differ
+        // by rounding
+        let exact = self.particle_spacing * 1e-6;
+
+        for m in self.symmetry.clone() {
+            let copy = symmetry::apply2(m, p);
+            if self.obstacles.as_ref().is_some_and(|o| o.blocks(copy)) {
+                continue;
+            }
+            if let Nearest::Particle(_, d) = self.index.nearest(&copy, self.search_radius()) {
+                if d < overlap {
+                    continue;
+                }
+            }
+
+            let parent_copy = symmetry::apply2(m, parent_position);
+            let parent = match self.index.nearest(&parent_copy, self.search_radius()) {
+                Nearest::Particle(i, d) if d < exact => i,
+                _ => continue,
+            };
+            if !self.can_join(copy, parent) {
+                continue;
+            }
+            self.add(copy, parent);
+        }
+    }
+
+    // Smooths the aggregate in place, see Relaxation. Returns the number of
+    // passes made.
+    pub fn relax(&mut self, relaxation: Relaxation) -> usize {
+        let mut points: Vec<_> = self.points.iter().map(|p| p.as_slice()).collect();
+        let passes = relaxation.run(&mut points, &self.parents, self.particle_spacing);
+        self.points = points.into_iter().map(Vec2d::from).collect();
+
+        self.index.clear();
+        self.radius = 0.;
+        for (id, p) in self.points.iter().enumerate() {
+            self.index.insert(*p, id);
+            self.radius = self.radius.max(p.length());
+        }
+        self.bounding_radius = self.radius + self.attraction_distance;
+        passes
+    }
+
+    // Turns the aggregate about the origin so its longest principal axis
+    // lines up with x, see Shape. The symmetry group and the axis of
+    // Sticking::Global are turned with it. Returns the angle turned by, in
+    // radians. Panics when obstacles or a stickiness map are set, which
+    // can't be turned.
+    pub fn rotate_to_principal(&mut self) -> f64 {
+        self.try_rotate_to_principal()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like rotate_to_principal, but returns an Error instead of panicking
+    // and leaves the aggregate as it was
+    pub fn try_rotate_to_principal(&mut self) -> Result<f64> {
+        if self.obstacles.is_some() || self.stickiness_map.is_some() {
+            return Err(Error::MasksNotRotatable);
+        }
+        let axis = Shape::new(self).axes[0];
+        let angle = -axis.y.atan2(axis.x);
+        let (sin, cos) = angle.sin_cos();
+        for p in self.points.iter_mut() {
+            *p = Vec2d::new(cos * p.x - sin * p.y, sin * p.x + cos * p.y);
+        }
+        let rotation = [[cos, -sin], [sin, cos]];
+        for m in self.symmetry.iter_mut() {
+            *m = symmetry::conjugate2(rotation, *m);
+        }
+        let around_z = [[cos, -sin, 0.], [sin, cos, 0.], [0., 0., 1.]];
+        self.constraints = self.constraints.rotated(around_z);
+
+        self.index.clear();
+        for (id, p) in self.points.iter().enumerate() {
+            self.index.insert(*p, id);
+        }
+        Ok(angle)
+    }
+
+    // Checks the aggregate for broken invariants with the default
+    // Tolerances, see Issue
+    pub fn validate(&self) -> Vec<Issue> {
+        self.validate_with(Tolerances::default())
+    }
+
+    pub fn validate_with(&self, tolerances: Tolerances) -> Vec<Issue> {
+        let points: Vec<_> = self.points.iter().map(|p| p.as_slice()).collect();
+        let mut issues = tolerances.check(&points, &self.parents, self.particle_spacing);
+
+        if self.index.size() != self.points.len() {
+            issues.push(Issue::IndexSize(self.index.size()));
+        }
+        for (i, p) in self.points.iter().enumerate() {
+            let finite = p.as_slice().iter().all(|x| x.is_finite());
+            if finite && !self.index.within(p, 1e-9).contains(&i) {
+                issues.push(Issue::NotIndexed(i));
+            }
+        }
+        issues
+    }
+
+    pub fn seeds(&self) -> &[usize] {
+        &self.seeds
+    }
+
+    // One entry per cluster, in the order of seeds
+    pub fn cluster_stats(&self) -> Vec<ClusterStats<Vec2d>> {
+        let mut stats: Vec<_> = self
+            .seeds
+            .iter()
+            .map(|&seed| ClusterStats {
+                seed,
+                particles: 0,
+                center: Vec2d::zero(),
+                radius_of_gyration: 0.,
+                extent: 0.,
+            })
+            .collect();
+        let mut squares = vec![0.; stats.len()];
+
+        for (p, &label) in self.points.iter().zip(&self.labels) {
+            let cluster = &mut stats[label];
+            cluster.particles += 1;
+            cluster.center += *p;
+            cluster.extent = cluster.extent.max(p.distance(self.points[cluster.seed]));
+            squares[label] += p.length_squared();
+        }
+
+        for (cluster, square) in stats.iter_mut().zip(squares) {
+            let n = cluster.particles as f64;
+            cluster.center = cluster.center / n;
+            cluster.radius_of_gyration = (square / n - cluster.center.length_squared())
+                .max(0.)
+                .sqrt();
+        }
+        stats
+    }
+
+    // Walkers closer than this to a particle always get an exact answer from
+    // the index, further away it may only tell how much room there is
+    fn search_radius(&self) -> f64 {
+        self.attraction_distance * 2.
+    }
+
+    // Moves the walker at p once, or adds it to the aggregate if it is
+    // close enough to join
+    fn step(&mut self, p: Vec2d) -> Step<Vec2d> {
+        match self.walk(p) {
+            Step::Sticking(parent, placed) => {
+                // add the point
+                self.add(placed, parent);
+                let parent = self.parents[self.points.len() - 1];
+                self.add_symmetric_copies(placed, parent);
+                Step::Joined
+            }
+            step => step,
+        }
+    }
+
+    // Like step, but leaves walkers that would join as Sticking
+    fn walk(&mut self, p: Vec2d) -> Step<Vec2d> {
+        let d = match self.index.nearest(&p, self.search_radius()) {
+            Nearest::Particle(parent, d) if d < self.attraction_distance => {
+                // adjust particle position in relation to its parent
+                let placed = self.place_particle(p, parent);
+
+                if self.can_join(placed, parent) && self.should_join(parent, placed) {
+                    return Step::Sticking(parent, placed);
+                }
+
+                // push particle away a bit, or walk on when that would
+                // cross an obstacle
+                let pushed = Vec2d::lerp(
+                    self.points[parent],
+                    p,
+                    self.attraction_distance + self.min_move_distanse,
+                );
+                if !self.crosses_obstacle(p, pushed) {
+                    return Step::Walking(pushed);
+                }
+                d
+            }
+            Nearest::Particle(_, d) | Nearest::Clear(d) => d,
+        };
+
+        // move randomly, without stepping over or into obstacles
+        let mut m = self.min_move_distanse.max(d - self.attraction_distance);
+        if let Some(obstacles) = &self.obstacles {
+            m = m.min(obstacles.clearance(p).max(self.min_move_distanse));
+        }
+        let next = p + self.motion_vector().normalized() * m;
+        if self.crosses_obstacle(p, next) {
+            return Step::Walking(p);
+        }
+        Step::Walking(next)
+    }
+
+    // Whether moving from p to next runs into an obstacle. Walkers that
+    // already are inside one may leave it.
+    fn crosses_obstacle(&self, p: Vec2d, next: Vec2d) -> bool {
+        self.obstacles
+            .as_ref()
+            .is_some_and(|o| !o.blocks(p) && o.crosses(p, next))
     }
 
     pub fn add_particle(&mut self) {
-        let mut p = self.random_starting_position();
+        if let Err(e) = self.try_add_particle() {
+            panic!("{}", e);
+        }
+    }
 
-        loop {
-            let parent = self.nearest(&p);
+    // Like add_particle, but returns an Error instead of panicking when
+    // there is nothing to grow from, or obstacles wall the aggregate in
+    pub fn try_add_particle(&mut self) -> Result<()> {
+        if self.points.is_empty() {
+            return Err(Error::Empty);
+        }
 
-            let d = p.distance(self.points[parent]);
+        self.apply_schedules();
 
-            if d < self.attraction_distance {
-                if !self.should_join(parent) {
-                    // push particle away a bit
-                    p = Vec2d::lerp(
-                        self.points[parent],
-                        p,
-                        self.attraction_distance + self.min_move_distanse,
-                    );
+        let mut p = self.launch_position().ok_or(Error::WalledIn)?;
+
+        while let Step::Walking(next) = self.step(p) {
+            p = next;
+
+            // check if particle is too far away, bring it back if so
+            if self.should_reset(p) {
+                p = self.return_to_launch_sphere(p);
+            }
+        }
+        Ok(())
+    }
+
+    // Launches as many test walkers as walkers one after another, which move
+    // and stick the way new particles would, and counts where they stick
+    // instead of adding them, see HarmonicMeasure. The aggregate is left as
+    // it was. Walkers obstacles keep from starting anywhere count as missed.
+    pub fn probe(&mut self, walkers: usize) -> HarmonicMeasure {
+        let mut hits = vec![0; self.points.len()];
+        let mut missed = 0;
+        if self.points.is_empty() {
+            return HarmonicMeasure::new(self, hits, missed);
+        }
+
+        // every test walker meets stubbornness as the aggregate is now, not
+        // worn down by the ones before it
+        let join_attempts = self.join_attempts.clone();
+        for _ in 0..walkers {
+            if self.stubbornness > 0 {
+                self.join_attempts.copy_from_slice(&join_attempts);
+            }
+
+            let mut p = match self.launch_position() {
+                Some(p) => p,
+                None => {
+                    missed += 1;
                     continue;
                 }
+            };
+            let parent = loop {
+                match self.walk(p) {
+                    Step::Walking(next) => p = next,
+                    Step::Sticking(parent, _) => break parent,
+                    Step::Joined => unreachable!("walk never adds particles"),
+                }
 
-                // adjust particle position in relation to its parent
-                p = self.place_particle(p, parent);
+                // check if particle is too far away, bring it back if so
+                if self.should_reset(p) {
+                    p = self.return_to_launch_sphere(p);
+                }
+            };
+            match hits.get_mut(parent) {
+                Some(count) => *count += 1,
+                None => missed += 1,
+            }
+        }
+        self.join_attempts = join_attempts;
 
-                // add the point
-                self.add(p, parent);
-                return;
+        HarmonicMeasure::new(self, hits, missed)
+    }
+
+    // A walker at a random place in the domain of walkers, away from the
+    // aggregate
+    fn spawn_walker(&self, walkers: &Walkers) -> Result<Vec2d> {
+        for _ in 0..MAX_SPAWN_ATTEMPTS {
+            let p = vec2d::random_in_unit_sphere() * walkers.radius();
+            if self.sealed_off(p) {
+                continue;
+            }
+            match self.index.nearest(&p, self.search_radius()) {
+                Nearest::Particle(_, d) if d < self.attraction_distance => continue,
+                _ => return Ok(p),
             }
+        }
+        Err(Error::DomainFull)
+    }
 
-            // move randomly
-            let m = self.min_move_distanse.max(d - self.attraction_distance);
-            p += self.motion_vector().normalized() * m;
+    // Grows the aggregate by at least particles with finite density of
+    // walkers, see Walkers. Stops early when the aggregate reaches the edge
+    // of the domain. Returns the number of particles added, or panics when
+    // the aggregate fills the domain so walkers can't be spawned away from
+    // it.
+    pub fn grow_dense(&mut self, walkers: Walkers, particles: usize) -> usize {
+        self.try_grow_dense(walkers, particles)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
 
-            // check if particle is too far away, reset if so
-            if self.should_reset(p) {
-                p = self.random_starting_position();
+    // Like grow_dense, but returns an Error instead of panicking. Particles
+    // added before DomainFull stay in the aggregate.
+    pub fn try_grow_dense(&mut self, walkers: Walkers, particles: usize) -> Result<usize> {
+        if self.points.is_empty() {
+            return Err(Error::Empty);
+        }
+        if walkers.count2d() == 0 {
+            return Err(Error::NoWalkers);
+        }
+
+        let start = self.points.len();
+        let mut crowd: Vec<(Vec2d, usize)> = (0..walkers.count2d())
+            .map(|_| Ok((self.spawn_walker(&walkers)?, 0)))
+            .collect::<Result<_>>()?;
+
+        while self.points.len() - start < particles && self.radius < walkers.radius() {
+            self.apply_schedules();
+
+            for walker in crowd.iter_mut() {
+                let (p, age) = *walker;
+                *walker = match self.step(p) {
+                    Step::Walking(next)
+                        if next.length() <= walkers.radius() && !walkers.expires(age + 1) =>
+                    {
+                        (next, age + 1)
+                    }
+                    // joined, left or expired, replace it
+                    _ => (self.spawn_walker(&walkers)?, 0),
+                };
+                if self.points.len() - start >= particles {
+                    break;
+                }
             }
         }
+        Ok(self.points.len() - start)
     }
 
     pub fn save_csv(&self, name: &str) -> std::io::Result<()> {
@@ -348,15 +1585,12 @@ impl FlatAggregation {
         let mut file = File::create(name)?;
         file.write_all(b"index,parent,x,y,z\n")?;
 
-        for (index, val) in self.index.iter().enumerate() {
-            file.write_all(
-                &format!(
-                    "{},{},{:.04},{:.04},{:.04}\n",
-                    index, val.index, val.vec.x, val.vec.y, 0.
-                )
-                .as_bytes(),
-            )
-            .expect("second write");
+        for (index, (val, parent)) in self.points.iter().zip(&self.parents).enumerate() {
+            writeln!(
+                file,
+                "{},{},{:.04},{:.04},{:.04}",
+                index, parent, val.x, val.y, 0.
+            )?;
         }
 
         Ok(())
@@ -370,3 +1604,51 @@ impl FlatAggregation {
     //     );
     // }
 }
+
+impl<I: SpatialIndex<Vec2d>> Aggregation for FlatAggregation<I> {
+    type Vector = Vec2d;
+
+    fn add(&mut self, p: Vec2d, parent: usize) {
+        self.add(p, parent)
+    }
+
+    fn add_particle(&mut self) {
+        self.add_particle()
+    }
+
+    fn try_add_particle(&mut self) -> Result<()> {
+        self.try_add_particle()
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn dimensions(&self) -> usize {
+        2
+    }
+
+    fn position(&self, index: usize) -> Vec3d {
+        self.points[index].into()
+    }
+
+    fn parent(&self, index: usize) -> usize {
+        self.parents[index]
+    }
+
+    fn within(&self, p: Vec3d, radius: f64) -> Vec<usize> {
+        self.index.within(&Vec2d::new(p.x, p.y), radius)
+    }
+
+    fn particle_spacing(&self) -> f64 {
+        self.particle_spacing
+    }
+
+    fn attraction_distance(&self) -> f64 {
+        self.attraction_distance
+    }
+
+    fn bounding_radius(&self) -> f64 {
+        self.bounding_radius
+    }
+}
@@ -0,0 +1,487 @@
+use image::DynamicImage;
+
+use std::path::Path;
+
+use crate::config::Invalid;
+use crate::error::{Error, Result};
+use crate::structures::Vec2d;
+
+// Mask is an image read as a field of values from 0 for black to 1 for
+// white, laid over the plane a FlatAggregation grows in. It drives
+// add_seeds, set_obstacles and set_stickiness_map.
+//
+// Colour images are read by their luminance, or by a single channel with
+// from_channel, so one picture can hold seeds, obstacles and stickiness in
+// its red, green and blue channels.
+//
+// By default the image is centered on the origin, one pixel is one unit
+// wide and rows run down the y axis, so the picture isn't upside down.
+// Center and pixel_size move and scale it. Points outside the image have
+// no value.
+#[derive(Clone, Debug)]
+pub struct Mask {
+    width: usize,
+    height: usize,
+    values: Vec<f64>,
+    center: Vec2d,
+    pixel_size: f64,
+}
+
+impl Mask {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Mask> {
+        Ok(Mask::from_image(&image::open(path)?))
+    }
+
+    pub fn from_image(image: &DynamicImage) -> Mask {
+        let image = image.to_luma();
+        let (width, height) = image.dimensions();
+        Mask::from_values(
+            width as usize,
+            height as usize,
+            image.pixels().map(|p| f64::from(p.0[0]) / 255.).collect(),
+        )
+    }
+
+    // Reads the channel-th channel, 0 to 3 for red, green, blue and alpha
+    pub fn from_channel(image: &DynamicImage, channel: usize) -> Mask {
+        Mask::try_from_channel(image, channel).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like from_channel, but returns an Error instead of panicking when
+    // there is no such channel
+    pub fn try_from_channel(image: &DynamicImage, channel: usize) -> Result<Mask> {
+        if channel > 3 {
+            return Err(Error::Config(vec![Invalid::Channel(channel)]));
+        }
+        let image = image.to_rgba();
+        let (width, height) = image.dimensions();
+        Mask::try_from_values(
+            width as usize,
+            height as usize,
+            image
+                .pixels()
+                .map(|p| f64::from(p.0[channel]) / 255.)
+                .collect(),
+        )
+    }
+
+    // Row by row from the top left corner, as in images
+    pub fn from_values(width: usize, height: usize, values: Vec<f64>) -> Mask {
+        Mask::try_from_values(width, height, values).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like from_values, but returns an Error instead of panicking when
+    // there are more or fewer values than pixels
+    pub fn try_from_values(width: usize, height: usize, values: Vec<f64>) -> Result<Mask> {
+        if values.len() != width * height {
+            return Err(Error::Config(vec![Invalid::MaskSize(values.len())]));
+        }
+        Ok(Mask {
+            width,
+            height,
+            values,
+            center: Vec2d::zero(),
+            pixel_size: 1.,
+        })
+    }
+
+    pub fn with_center(mut self, a: Vec2d) -> Mask {
+        self.center = a;
+        self
+    }
+
+    pub fn with_pixel_size(mut self, a: f64) -> Mask {
+        self.pixel_size = a;
+        self
+    }
+
+    // Swaps black and white, for dark shapes on a light background
+    pub fn inverted(mut self) -> Mask {
+        for v in self.values.iter_mut() {
+            *v = 1. - *v;
+        }
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel_size(&self) -> f64 {
+        self.pixel_size
+    }
+
+    // Column and row of the pixel p lies in
+    fn pixel(&self, p: Vec2d) -> Option<(usize, usize)> {
+        let column = ((p.x - self.center.x) / self.pixel_size + self.width as f64 / 2.).floor();
+        let row = (self.height as f64 / 2. - (p.y - self.center.y) / self.pixel_size).floor();
+        if column >= 0. && row >= 0. && column < self.width as f64 && row < self.height as f64 {
+            Some((column as usize, row as usize))
+        } else {
+            None
+        }
+    }
+
+    // Where the center of a pixel lies
+    fn position(&self, column: usize, row: usize) -> Vec2d {
+        Vec2d::new(
+            (column as f64 + 0.5 - self.width as f64 / 2.) * self.pixel_size + self.center.x,
+            (self.height as f64 / 2. - row as f64 - 0.5) * self.pixel_size + self.center.y,
+        )
+    }
+
+    pub fn value(&self, p: Vec2d) -> Option<f64> {
+        self.pixel(p)
+            .map(|(column, row)| self.values[row * self.width + column])
+    }
+
+    // Centers of the pixels brighter than threshold
+    pub fn positions_over(&self, threshold: f64) -> Vec<Vec2d> {
+        let mut positions = Vec::new();
+        for row in 0..self.height {
+            for column in 0..self.width {
+                if self.values[row * self.width + column] > threshold {
+                    positions.push(self.position(column, row));
+                }
+            }
+        }
+        positions
+    }
+
+    // Distance from p to the image, zero inside it
+    fn distance_outside(&self, p: Vec2d) -> f64 {
+        let half_width = self.width as f64 * self.pixel_size / 2.;
+        let half_height = self.height as f64 * self.pixel_size / 2.;
+        let dx = ((p.x - self.center.x).abs() - half_width).max(0.);
+        let dy = ((p.y - self.center.y).abs() - half_height).max(0.);
+        (dx * dx + dy * dy).sqrt()
+    }
+}
+
+// Pixels of a mask brighter than a threshold that walkers can't enter,
+// with the distance from every pixel to the closest of them so walkers
+// far away can take long steps.
+//
+// Pockets numbers the regions of free pixels that walls of obstacles
+// separate, 0 for the one walkers can reach from outside the mask and for
+// blocked pixels. Reached tells which regions hold a particle, walkers
+// started in the others could never join.
+pub(crate) struct Obstacles {
+    mask: Mask,
+    blocked: Vec<bool>,
+    clearance: Vec<f64>,
+    pockets: Vec<usize>,
+    reached: Vec<bool>,
+}
+
+// Stands in for infinity, which the distance transform can't subtract
+const FAR: f64 = 1e20;
+
+impl Obstacles {
+    pub(crate) fn new(mask: Mask, threshold: f64) -> Obstacles {
+        let blocked: Vec<bool> = mask.values.iter().map(|&v| v > threshold).collect();
+        let (width, height) = (mask.width, mask.height);
+
+        // squared distances in pixels, columns first and then rows
+        let mut clearance: Vec<f64> = blocked.iter().map(|&b| if b { 0. } else { FAR }).collect();
+        let mut line = Vec::new();
+        for column in 0..width {
+            line.clear();
+            line.extend((0..height).map(|row| clearance[row * width + column]));
+            for (row, d) in distance_transform(&line).into_iter().enumerate() {
+                clearance[row * width + column] = d;
+            }
+        }
+        for row in clearance.chunks_mut(width.max(1)) {
+            let transformed = distance_transform(row);
+            row.copy_from_slice(&transformed);
+        }
+
+        // from anywhere in a pixel to the closest corner of an obstacle
+        let pixel_size = mask.pixel_size;
+        for d in clearance.iter_mut() {
+            *d = if *d >= FAR {
+                f64::INFINITY
+            } else {
+                (d.sqrt() - std::f64::consts::SQRT_2).max(0.) * pixel_size
+            };
+        }
+
+        let pockets = pockets(&blocked, width, height);
+        let reached = vec![false; pockets.iter().max().map_or(1, |&most| most + 1)];
+
+        Obstacles {
+            mask,
+            blocked,
+            clearance,
+            pockets,
+            reached,
+        }
+    }
+
+    fn pocket(&self, p: Vec2d) -> usize {
+        self.mask.pixel(p).map_or(0, |(column, row)| {
+            self.pockets[row * self.mask.width + column]
+        })
+    }
+
+    // Notes a particle at p, which opens its region to walkers
+    pub(crate) fn reach(&mut self, p: Vec2d) {
+        let pocket = self.pocket(p);
+        self.reached[pocket] = true;
+    }
+
+    // Whether p lies in a region without particles, walled off from all of
+    // them. Walkers inside obstacles may leave them anywhere.
+    pub(crate) fn sealed_off(&self, p: Vec2d) -> bool {
+        !self.blocks(p) && !self.reached[self.pocket(p)]
+    }
+
+    pub(crate) fn blocks(&self, p: Vec2d) -> bool {
+        self.mask
+            .pixel(p)
+            .is_some_and(|(column, row)| self.blocked[row * self.mask.width + column])
+    }
+
+    // Whether moving straight from p to q touches an obstacle on the way.
+    // Moves longer than the clearance march through every pixel they pass,
+    // so they can't jump over a line one pixel thin, and a move exactly
+    // through a corner touches the pixels on both sides of it.
+    pub(crate) fn crosses(&self, p: Vec2d, q: Vec2d) -> bool {
+        if p.distance(q) <= self.clearance(p) {
+            return false;
+        }
+
+        // in pixels, columns along x and rows along y
+        let mask = &self.mask;
+        let pixels = |p: Vec2d| {
+            (
+                (p.x - mask.center.x) / mask.pixel_size + mask.width as f64 / 2.,
+                mask.height as f64 / 2. - (p.y - mask.center.y) / mask.pixel_size,
+            )
+        };
+        let blocked = |column: i64, row: i64| {
+            column >= 0
+                && row >= 0
+                && (column as usize) < mask.width
+                && (row as usize) < mask.height
+                && self.blocked[row as usize * mask.width + column as usize]
+        };
+        let ((x0, y0), (x1, y1)) = (pixels(p), pixels(q));
+        let (mut column, mut row) = (x0.floor() as i64, y0.floor() as i64);
+        let step_column = if x1 > x0 { 1 } else { -1 };
+        let step_row = if y1 > y0 { 1 } else { -1 };
+
+        // share of the move to the next column and row boundary, and
+        // between boundaries
+        let across = |from: f64, to: f64| {
+            if to == from {
+                (f64::INFINITY, f64::INFINITY)
+            } else {
+                let delta = 1. / (to - from).abs();
+                let offset = if to > from {
+                    from.floor() + 1. - from
+                } else {
+                    from - from.floor()
+                };
+                (offset * delta, delta)
+            }
+        };
+        let (mut next_x, delta_x) = across(x0, x1);
+        let (mut next_y, delta_y) = across(y0, y1);
+
+        loop {
+            if blocked(column, row) {
+                return true;
+            }
+            if next_x.min(next_y) > 1. {
+                return false;
+            }
+            if next_x < next_y {
+                column += step_column;
+                next_x += delta_x;
+            } else if next_y < next_x {
+                row += step_row;
+                next_y += delta_y;
+            } else {
+                if blocked(column + step_column, row) || blocked(column, row + step_row) {
+                    return true;
+                }
+                column += step_column;
+                row += step_row;
+                next_x += delta_x;
+                next_y += delta_y;
+            }
+        }
+    }
+
+    // How far p is from any obstacle, at least
+    pub(crate) fn clearance(&self, p: Vec2d) -> f64 {
+        match self.mask.pixel(p) {
+            Some((column, row)) => self.clearance[row * self.mask.width + column],
+            None => self.mask.distance_outside(p),
+        }
+    }
+}
+
+// Numbers the regions of free pixels that don't touch the edge of the
+// image, see Obstacles. Walkers can't pass between diagonal neighbours, so
+// regions only connect through the sides of pixels.
+fn pockets(blocked: &[bool], width: usize, height: usize) -> Vec<usize> {
+    let mut pockets: Vec<usize> = blocked
+        .iter()
+        .map(|&b| if b { 0 } else { UNSEEN })
+        .collect();
+
+    // the edge first, everything connected to it stays open
+    for i in 0..pockets.len() {
+        let (column, row) = (i % width, i / width);
+        if column == 0 || row == 0 || column + 1 == width || row + 1 == height {
+            fill(&mut pockets, i, 0, width, height);
+        }
+    }
+    let mut next = 1;
+    for i in 0..pockets.len() {
+        if pockets[i] == UNSEEN {
+            fill(&mut pockets, i, next, width, height);
+            next += 1;
+        }
+    }
+    pockets
+}
+
+// Free pixels not numbered by pockets yet
+const UNSEEN: usize = usize::MAX;
+
+// Numbers the unseen pixels connected to start, start included, as pocket
+fn fill(pockets: &mut [usize], start: usize, pocket: usize, width: usize, height: usize) {
+    if pockets[start] != UNSEEN {
+        return;
+    }
+    pockets[start] = pocket;
+    let mut stack = vec![start];
+    while let Some(i) = stack.pop() {
+        let (column, row) = (i % width, i / width);
+        let mut neighbours = Vec::with_capacity(4);
+        if column > 0 {
+            neighbours.push(i - 1);
+        }
+        if column + 1 < width {
+            neighbours.push(i + 1);
+        }
+        if row > 0 {
+            neighbours.push(i - width);
+        }
+        if row + 1 < height {
+            neighbours.push(i + width);
+        }
+        for j in neighbours {
+            if pockets[j] == UNSEEN {
+                pockets[j] = pocket;
+                stack.push(j);
+            }
+        }
+    }
+}
+
+// Squared distance from every entry to the closest zero one along a line,
+// where f holds zero or FAR, by Felzenszwalb and Huttenlocher's lower
+// envelope of parabolas
+fn distance_transform(f: &[f64]) -> Vec<f64> {
+    let n = f.len();
+    let mut d = vec![0.; n];
+    if n == 0 {
+        return d;
+    }
+    let mut v = vec![0; n];
+    let mut z = vec![0.; n + 1];
+    let mut k = 0;
+    z[0] = f64::NEG_INFINITY;
+    z[1] = f64::INFINITY;
+
+    let parabola = |q: usize| f[q] + (q * q) as f64;
+    for q in 1..n {
+        loop {
+            let s = (parabola(q) - parabola(v[k])) / (2 * (q - v[k])) as f64;
+            if s <= z[k] {
+                k -= 1;
+            } else {
+                k += 1;
+                v[k] = q;
+                z[k] = s;
+                z[k + 1] = f64::INFINITY;
+                break;
+            }
+        }
+    }
+
+    k = 0;
+    for (q, x) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        let offset = q as f64 - v[k] as f64;
+        *x = offset * offset + f[v[k]];
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A mask with the listed pixels, as (column, row), set to 1
+    fn obstacles(width: usize, height: usize, pixels: &[(usize, usize)]) -> Obstacles {
+        let mut values = vec![0.; width * height];
+        for &(column, row) in pixels {
+            values[row * width + column] = 1.;
+        }
+        Obstacles::new(Mask::from_values(width, height, values), 0.5)
+    }
+
+    #[test]
+    fn distance_transform_squares_distances_to_zeros() {
+        let f = [FAR, 0., FAR, FAR, FAR, 0.];
+        assert_eq!(distance_transform(&f), vec![1., 0., 1., 4., 1., 0.]);
+    }
+
+    #[test]
+    fn walkers_cant_jump_a_diagonal_line() {
+        let diagonal: Vec<_> = (0..5).map(|i| (i, i)).collect();
+        let o = obstacles(5, 5, &diagonal);
+        let center = |column, row| o.mask.position(column, row);
+
+        assert!(o.crosses(center(3, 1), center(1, 3)));
+        // exactly through the corner between (1, 1) and (2, 2)
+        assert!(o.crosses(center(2, 1), center(1, 2)));
+        // through a corner with free pixels on both sides
+        assert!(!o.crosses(center(3, 0), center(4, 1)));
+    }
+
+    #[test]
+    fn walled_in_pixels_are_sealed_off_until_reached() {
+        let ring = [
+            (1, 1),
+            (2, 1),
+            (3, 1),
+            (1, 2),
+            (3, 2),
+            (1, 3),
+            (2, 3),
+            (3, 3),
+        ];
+        let mut o = obstacles(5, 5, &ring);
+        let inside = o.mask.position(2, 2);
+        let outside = Vec2d::new(10., 10.);
+
+        assert!(o.sealed_off(inside) && o.sealed_off(outside));
+        o.reach(inside);
+        assert!(!o.sealed_off(inside) && o.sealed_off(outside));
+        o.reach(outside);
+        assert!(!o.sealed_off(o.mask.position(0, 0)));
+    }
+}
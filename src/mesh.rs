@@ -0,0 +1,335 @@
+use rstar::{PointDistance, RTree, RTreeObject, AABB};
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::structures::Vec3d;
+use crate::symmetry::{apply3, Matrix3};
+
+// Mesh is a triangle soup loaded from an OBJ or STL file, to grow a
+// ConvexAggregation on or around, see set_mesh.
+//
+// Polygons with more than three corners are split into triangles around
+// their first corner. Only the positions of OBJ files are read, normals,
+// texture coordinates and materials are skipped.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vec3d>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+// MeshMode decides what walkers do when they reach the surface of a mesh.
+//
+// Seed makes the whole surface sticky. Walkers that reach it join right
+// there as new seeds, so the aggregate grows over the model.
+//
+// Obstacle makes the surface bounce walkers off like rejected joins, growth
+// then only starts from seeds added by hand and flows around the model.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MeshMode {
+    Seed,
+    Obstacle,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Mesh {
+    // Reads an OBJ or STL file, told apart by the extension
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Mesh> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let file = File::open(path)?;
+        match extension.as_deref() {
+            Some("obj") => Mesh::from_obj(BufReader::new(file)),
+            Some("stl") => Mesh::from_stl(BufReader::new(file)),
+            _ => Err(invalid(format!(
+                "{} is neither .obj nor .stl",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn from_obj<R: BufRead>(reader: R) -> io::Result<Mesh> {
+        let mut mesh = Mesh::default();
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+            let bad = || invalid(format!("bad obj line {}: {}", number + 1, line));
+
+            match tokens.next() {
+                Some("v") => {
+                    let mut c = [0.; 3];
+                    for x in c.iter_mut() {
+                        *x = tokens.next().and_then(|t| t.parse().ok()).ok_or_else(bad)?;
+                    }
+                    mesh.vertices.push(c.into());
+                }
+                Some("f") => {
+                    let mut corners = Vec::new();
+                    for token in tokens {
+                        // v, v/vt, v//vn or v/vt/vn, negative ones count back
+                        // from the last vertex
+                        let i: i64 = token
+                            .split('/')
+                            .next()
+                            .and_then(|t| t.parse().ok())
+                            .ok_or_else(bad)?;
+                        let n = mesh.vertices.len() as i64;
+                        let index = if i < 0 { n + i } else { i - 1 };
+                        if index < 0 || index >= n {
+                            return Err(bad());
+                        }
+                        corners.push(index as usize);
+                    }
+                    if corners.len() < 3 {
+                        return Err(bad());
+                    }
+                    for k in 1..corners.len() - 1 {
+                        mesh.triangles
+                            .push([corners[0], corners[k], corners[k + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(mesh)
+    }
+
+    // Binary and ASCII STL. Binary files may start with "solid" too, so a
+    // file is taken as binary whenever its size matches its triangle count.
+    pub fn from_stl<R: Read>(mut reader: R) -> io::Result<Mesh> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        if data.len() >= 84 {
+            let mut count = [0; 4];
+            count.copy_from_slice(&data[80..84]);
+            let count = u32::from_le_bytes(count) as usize;
+            if data.len() == 84 + count * 50 {
+                return Ok(Mesh::from_binary_stl(&data[84..], count));
+            }
+        }
+
+        let text = String::from_utf8(data).map_err(|_| invalid("bad stl file".into()))?;
+        let mut mesh = Mesh::default();
+        for line in text.lines() {
+            let mut tokens = line.split_whitespace();
+            if tokens.next() != Some("vertex") {
+                continue;
+            }
+            let mut c = [0.; 3];
+            for x in c.iter_mut() {
+                *x = tokens
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .ok_or_else(|| invalid(format!("bad stl line: {}", line)))?;
+            }
+            mesh.vertices.push(c.into());
+        }
+        if mesh.vertices.len() % 3 != 0 {
+            return Err(invalid("stl facets need three vertices".into()));
+        }
+        mesh.triangles = (0..mesh.vertices.len() / 3)
+            .map(|t| [3 * t, 3 * t + 1, 3 * t + 2])
+            .collect();
+
+        Ok(mesh)
+    }
+
+    // Records of a normal and three corners as little endian f32, followed
+    // by two unused bytes
+    fn from_binary_stl(records: &[u8], count: usize) -> Mesh {
+        let mut mesh = Mesh::default();
+        for record in records.chunks_exact(50).take(count) {
+            for corner in 0..3 {
+                let mut c = [0.; 3];
+                for (axis, x) in c.iter_mut().enumerate() {
+                    let at = 12 + corner * 12 + axis * 4;
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(&record[at..at + 4]);
+                    *x = f64::from(f32::from_le_bytes(bytes));
+                }
+                mesh.vertices.push(c.into());
+            }
+            let t = mesh.vertices.len() - 3;
+            mesh.triangles.push([t, t + 1, t + 2]);
+        }
+        mesh
+    }
+
+    // Center and radius of a sphere around every vertex, not the smallest
+    // one but close to it
+    pub fn bounding_sphere(&self) -> (Vec3d, f64) {
+        if self.vertices.is_empty() {
+            return (Vec3d::zero(), 0.);
+        }
+        let mut min = self.vertices[0];
+        let mut max = self.vertices[0];
+        for &v in &self.vertices {
+            min = min.min(v);
+            max = max.max(v);
+        }
+        let center = (min + max) / 2.;
+        let radius = self
+            .vertices
+            .iter()
+            .map(|v| v.distance(center))
+            .fold(0., f64::max);
+        (center, radius)
+    }
+
+    // Moves and scales the mesh to fit in a sphere of radius around the
+    // origin, where aggregates grow
+    pub fn fit(&mut self, radius: f64) {
+        let (center, r) = self.bounding_sphere();
+        let scale = if r > 0. { radius / r } else { 1. };
+        for v in self.vertices.iter_mut() {
+            *v = (*v - center) * scale;
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Triangle {
+    corners: [Vec3d; 3],
+}
+
+impl RTreeObject for Triangle {
+    type Envelope = AABB<[f64; 3]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        let [a, b, c] = self.corners;
+        AABB::from_corners(a.min(b).min(c).as_slice(), a.max(b).max(c).as_slice())
+    }
+}
+
+impl PointDistance for Triangle {
+    fn distance_2(&self, point: &[f64; 3]) -> f64 {
+        let p = Vec3d::from(*point);
+        (self.closest_point(p) - p).length_squared()
+    }
+}
+
+impl Triangle {
+    // Closest point of the triangle to p, from Ericson's Real-Time Collision
+    // Detection
+    fn closest_point(&self, p: Vec3d) -> Vec3d {
+        let [a, b, c] = self.corners;
+        let ab = b - a;
+        let ac = c - a;
+
+        let ap = p - a;
+        let d1 = ab.dot(ap);
+        let d2 = ac.dot(ap);
+        if d1 <= 0. && d2 <= 0. {
+            return a;
+        }
+
+        let bp = p - b;
+        let d3 = ab.dot(bp);
+        let d4 = ac.dot(bp);
+        if d3 >= 0. && d4 <= d3 {
+            return b;
+        }
+
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0. && d1 >= 0. && d3 <= 0. {
+            return a + ab * (d1 / (d1 - d3));
+        }
+
+        let cp = p - c;
+        let d5 = ab.dot(cp);
+        let d6 = ac.dot(cp);
+        if d6 >= 0. && d5 <= d6 {
+            return c;
+        }
+
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0. && d2 >= 0. && d6 <= 0. {
+            return a + ac * (d2 / (d2 - d6));
+        }
+
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0. && d4 - d3 >= 0. && d5 - d6 >= 0. {
+            return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+        }
+
+        let denom = 1. / (va + vb + vc);
+        a + ab * (vb * denom) + ac * (vc * denom)
+    }
+}
+
+// The surface of a mesh as seen by walkers
+pub(crate) struct MeshSurface {
+    pub(crate) mode: MeshMode,
+    pub(crate) radius: f64,
+    triangles: RTree<Triangle>,
+}
+
+impl MeshSurface {
+    pub(crate) fn new(mesh: &Mesh, mode: MeshMode) -> MeshSurface {
+        let triangles: Vec<Triangle> = mesh
+            .triangles
+            .iter()
+            .map(|t| Triangle {
+                corners: [
+                    mesh.vertices[t[0]],
+                    mesh.vertices[t[1]],
+                    mesh.vertices[t[2]],
+                ],
+            })
+            // slivers without area have no closest point to speak of
+            .filter(|t| {
+                let [a, b, c] = t.corners;
+                (b - a).cross(c - a).length_squared() > 0.
+            })
+            .collect();
+        let radius = mesh.vertices.iter().map(|v| v.length()).fold(0., f64::max);
+
+        MeshSurface {
+            mode,
+            radius,
+            triangles: RTree::bulk_load(triangles),
+        }
+    }
+
+    // The same surface turned about the origin by m
+    pub(crate) fn rotated(&self, m: Matrix3) -> MeshSurface {
+        let triangles: Vec<Triangle> = self
+            .triangles
+            .iter()
+            .map(|t| Triangle {
+                corners: [
+                    apply3(m, t.corners[0]),
+                    apply3(m, t.corners[1]),
+                    apply3(m, t.corners[2]),
+                ],
+            })
+            .collect();
+
+        MeshSurface {
+            mode: self.mode,
+            radius: self.radius,
+            triangles: RTree::bulk_load(triangles),
+        }
+    }
+
+    // The point of the surface closest to p, and the distance to it
+    pub(crate) fn closest(&self, p: Vec3d) -> Option<(Vec3d, f64)> {
+        self.triangles
+            .nearest_neighbor_iter(&p.as_slice())
+            .next()
+            .map(|t| {
+                let q = t.closest_point(p);
+                (q, q.distance(p))
+            })
+    }
+}
@@ -30,9 +30,9 @@ impl Color {
 
     pub fn gamma_rgb(&self, gamma_correction: f64) -> image::Rgb<u8> {
         image::Rgb([
-            (self.r.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u8,
-            (self.g.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u8,
-            (self.b.min(1.0).max(0.0).powf(gamma_correction) * 255.0) as u8,
+            (self.r.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u8,
+            (self.g.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u8,
+            (self.b.clamp(0.0, 1.0).powf(gamma_correction) * 255.0) as u8,
         ])
     }
 }
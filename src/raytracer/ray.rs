@@ -21,7 +21,7 @@ pub struct Intersection {
 
 impl Ray {
     pub fn intersect(ray: Ray, objects: &[Box<dyn Intersectable>]) -> Option<Intersection> {
-        let mut distance = std::f64::INFINITY;
+        let mut distance = f64::INFINITY;
         let mut material = Material::neutral();
         let mut normal = Vec3::zero();
         let mut hit_point = Vec3::zero();
@@ -38,7 +38,7 @@ impl Ray {
             }
         }
 
-        if distance < std::f64::INFINITY {
+        if distance < f64::INFINITY {
             Some(Intersection {
                 distance,
                 hit_point,
@@ -1,3 +1,4 @@
+use crate::aggregation::Aggregation;
 use crate::raytracer;
 use crate::raytracer::camera::Camera;
 use crate::raytracer::color::Color;
@@ -18,15 +19,27 @@ use std::time::Instant;
 pub struct Raycaster {
     color: fn(f64) -> [f64; 3],
     points: Vec<Vec3d>,
+    shades: Option<Vec<f64>>,
     width: u32,
     height: u32,
 }
 
 impl Raycaster {
+    pub fn new<A: Aggregation>(model: &A) -> Raycaster {
+        Raycaster {
+            color: |_x| [1., 1., 1.],
+            points: model.positions(),
+            shades: None,
+            width: 1920,
+            height: 1080,
+        }
+    }
+
     pub fn flat(vec: RTree<crate::structures::IndexValue2d>) -> Raycaster {
         Raycaster {
             color: |_x| [1., 1., 1.],
             points: vec.iter().map(|x| Vec3d::from(x.vec)).collect(),
+            shades: None,
             width: 1920,
             height: 1080,
         }
@@ -35,6 +48,7 @@ impl Raycaster {
         Raycaster {
             color: |_x| [1., 1., 1.],
             points: vec.iter().map(|x| x.vec).collect(),
+            shades: None,
             width: 1920,
             height: 1080,
         }
@@ -48,6 +62,15 @@ impl Raycaster {
         }
     }
 
+    // Colours every particle by its shade, from 0 to 1, rather than by its
+    // distance from the center, see HarmonicMeasure::shades
+    pub fn with_shades(self, shades: Vec<f64>) -> Raycaster {
+        Raycaster {
+            shades: Some(shades),
+            ..self
+        }
+    }
+
     pub fn w_h(self, width: u32, height: u32) -> Raycaster {
         Raycaster {
             width,
@@ -57,13 +80,55 @@ impl Raycaster {
     }
 
     pub fn render(&self, name: &str) {
-        render(&self.points, name, self.color, self.width, self.height)
+        self.try_render(name).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_render(&self, name: &str) -> crate::Result<()> {
+        match &self.shades {
+            Some(shades) => try_render_shaded(
+                &self.points,
+                shades,
+                name,
+                self.color,
+                self.width,
+                self.height,
+            ),
+            None => try_render(&self.points, name, self.color, self.width, self.height),
+        }
     }
 }
 
 pub fn render(vec: &[Vec3d], name: &str, col: impl Fn(f64) -> [f64; 3], width: u32, height: u32) {
+    try_render(vec, name, col, width, height).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_render(
+    vec: &[Vec3d],
+    name: &str,
+    col: impl Fn(f64) -> [f64; 3],
+    width: u32,
+    height: u32,
+) -> crate::Result<()> {
+    let radius = vec.iter().fold(f64::EPSILON, |a, &b| {
+        a.max((b.x * b.x + b.y * b.y + b.z * b.z).sqrt())
+    });
+    let shades: Vec<f64> = vec.iter().map(|coord| coord.length() / radius).collect();
+    try_render_shaded(vec, &shades, name, col, width, height)
+}
+
+// Like try_render, but colours every particle by the shade at its index
+// rather than by its distance from the center. Particles past the end of
+// shades get 0.
+pub fn try_render_shaded(
+    vec: &[Vec3d],
+    shades: &[f64],
+    name: &str,
+    col: impl Fn(f64) -> [f64; 3],
+    width: u32,
+    height: u32,
+) -> crate::Result<()> {
     println!("Rendering");
-    let radius = vec.iter().fold(std::f64::EPSILON, |a, &b| {
+    let radius = vec.iter().fold(f64::EPSILON, |a, &b| {
         a.max((b.x * b.x + b.y * b.y + b.z * b.z).sqrt())
     });
 
@@ -82,14 +147,15 @@ pub fn render(vec: &[Vec3d], name: &str, col: impl Fn(f64) -> [f64; 3], width: u
     let aspect_ratio = f64::from(width) / f64::from(height);
 
     let mut plane: std::vec::Vec<
-        std::boxed::Box<(dyn raytracer::intersectable::Intersectable + 'static)>,
+        std::boxed::Box<dyn raytracer::intersectable::Intersectable + 'static>,
     > = Vec::new();
-    for coord in vec {
+    for (i, coord) in vec.iter().enumerate() {
+        let shade = shades.get(i).copied().unwrap_or(0.);
         plane.push(Box::new(Sphere {
             position: Vec3::new(coord.x, coord.y, coord.z),
             radius: 0.5,
             material: Material {
-                color: col(coord.length() / radius).into(),
+                color: col(shade).into(),
                 diffuse: 0.6,
                 specular: 50.0,
                 specular_exponent: 100.0,
@@ -141,7 +207,7 @@ pub fn render(vec: &[Vec3d], name: &str, col: impl Fn(f64) -> [f64; 3], width: u
 
     let now = Instant::now();
 
-    scene.render(name.to_string());
+    scene.try_render(name.to_string())?;
 
     let duration = now.elapsed();
 
@@ -151,4 +217,5 @@ pub fn render(vec: &[Vec3d], name: &str, col: impl Fn(f64) -> [f64; 3], width: u
     );
 
     // println!("{}:{}:{}", duration.);
+    Ok(())
 }
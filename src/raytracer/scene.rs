@@ -19,6 +19,10 @@ pub struct Scene {
 
 impl Scene {
     pub fn render(&self, filename: String) {
+        self.try_render(filename).unwrap();
+    }
+
+    pub fn try_render(&self, filename: String) -> std::io::Result<()> {
         let mut imgbuf = image::ImageBuffer::new(self.width, self.height);
 
         let gamma_correction = self.options.gamma.recip();
@@ -39,6 +43,6 @@ impl Scene {
             *pixel = color.gamma_rgb(gamma_correction);
         }
 
-        imgbuf.save(filename).unwrap();
+        imgbuf.save(filename)
     }
 }
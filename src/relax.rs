@@ -0,0 +1,121 @@
+use crate::structures::grid::{distance, Grid};
+
+// Relaxation settings for relax on the aggregations, which smooths a grown
+// aggregate in place.
+//
+// Every parent link acts as a spring with a rest length of
+// particle_spacing, and particles that aren't linked but sit closer than
+// particle_spacing push each other apart. Seeds stay where they are.
+//
+// Iterations caps the number of passes, relaxation stops early once no
+// particle moves by more than tolerance in a pass.
+//
+// Stiffness, between 0 and 1, is the share of every correction applied per
+// pass. Lower values converge slower but don't overshoot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Relaxation {
+    iterations: usize,
+    tolerance: f64,
+    stiffness: f64,
+}
+
+impl Default for Relaxation {
+    fn default() -> Relaxation {
+        Relaxation {
+            iterations: 100,
+            tolerance: 1e-3,
+            stiffness: 0.5,
+        }
+    }
+}
+
+impl Relaxation {
+    pub fn new() -> Relaxation {
+        Relaxation::default()
+    }
+
+    pub fn with_iterations(mut self, a: usize) -> Relaxation {
+        self.iterations = a;
+        self
+    }
+
+    pub fn with_tolerance(mut self, a: f64) -> Relaxation {
+        self.tolerance = a;
+        self
+    }
+
+    pub fn with_stiffness(mut self, a: f64) -> Relaxation {
+        self.stiffness = a;
+        self
+    }
+
+    // Relaxes points linked by parents, returns the number of passes made
+    pub(crate) fn run<const N: usize>(
+        &self,
+        points: &mut [[f64; N]],
+        parents: &[usize],
+        spacing: f64,
+    ) -> usize {
+        let mut moves = vec![([0.; N], 0usize); points.len()];
+
+        for pass in 0..self.iterations {
+            for m in moves.iter_mut() {
+                *m = ([0.; N], 0);
+            }
+
+            // pulls a and b towards being `spacing` apart, half each
+            let mut correct = |a: usize, b: usize, points: &[[f64; N]]| {
+                let d = distance(&points[a], &points[b]);
+                if d == 0. {
+                    return;
+                }
+                let f = 0.5 * (d - spacing) / d;
+                for (i, (x, y)) in points[a].iter().zip(&points[b]).enumerate() {
+                    let c = f * (y - x);
+                    moves[a].0[i] += c;
+                    moves[b].0[i] -= c;
+                }
+                moves[a].1 += 1;
+                moves[b].1 += 1;
+            };
+
+            for (i, &parent) in parents.iter().enumerate() {
+                if parent != i {
+                    correct(i, parent, points);
+                }
+            }
+
+            let mut grid = Grid::<N, usize>::new(spacing);
+            for (i, p) in points.iter().enumerate() {
+                grid.insert(*p, i);
+            }
+            for (i, p) in points.iter().enumerate() {
+                for j in grid.within(p, spacing, &|&j| (points[j], j)) {
+                    let linked = parents[i] == j || parents[j] == i;
+                    if j > i && !linked && distance(p, &points[j]) < spacing {
+                        correct(i, j, points);
+                    }
+                }
+            }
+
+            let mut largest: f64 = 0.;
+            for (i, (p, (m, count))) in points.iter_mut().zip(&moves).enumerate() {
+                if parents[i] == i || *count == 0 {
+                    continue;
+                }
+                let scale = self.stiffness / *count as f64;
+                let mut step = 0.;
+                for (x, c) in p.iter_mut().zip(m) {
+                    *x += c * scale;
+                    step += (c * scale) * (c * scale);
+                }
+                largest = largest.max(step);
+            }
+
+            if largest.sqrt() < self.tolerance {
+                return pass + 1;
+            }
+        }
+        self.iterations
+    }
+}
@@ -0,0 +1,129 @@
+use crate::config::Invalid;
+use crate::error::{Error, Result};
+
+// A Schedule makes a growth parameter follow the progress of a run instead
+// of staying fixed, see set_schedule on the aggregations. Scheduled values
+// are looked up before every new walker is launched, and overwrite
+// whatever the parameter's set_* was given.
+//
+// Driver picks what the schedule follows, the number of particles in the
+// aggregate or its radius, the distance of the furthest particle from the
+// origin.
+//
+// Linear, exponential and keyframed schedules hold their first and last
+// values before and after the range they span. Exponential ones
+// interpolate the logarithm of the value, so they need positive values.
+// Function schedules have to be Send and Sync, so aggregations holding them
+// can still move between threads.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Parameter {
+    ParticleSpacing,
+    AttractionDistance,
+    MinMoveDistance,
+    Stubbornness,
+    Stickiness,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Driver {
+    Count,
+    Radius,
+}
+
+pub struct Schedule {
+    driver: Driver,
+    curve: Curve,
+}
+
+enum Curve {
+    Linear(Vec<(f64, f64)>),
+    Exponential(Vec<(f64, f64)>),
+    Function(Box<dyn Fn(f64) -> f64 + Send + Sync>),
+}
+
+impl Schedule {
+    // Goes from start.1 at start.0 to end.1 at end.0
+    pub fn linear(driver: Driver, start: (f64, f64), end: (f64, f64)) -> Schedule {
+        Schedule::keyframes(driver, vec![start, end])
+    }
+
+    pub fn exponential(driver: Driver, start: (f64, f64), end: (f64, f64)) -> Schedule {
+        Schedule::try_exponential(driver, start, end).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like exponential, but returns an Error instead of panicking when a
+    // value isn't positive
+    pub fn try_exponential(driver: Driver, start: (f64, f64), end: (f64, f64)) -> Result<Schedule> {
+        let invalid: Vec<_> = [start.1, end.1]
+            .iter()
+            .filter(|&&v| v.is_nan() || v <= 0.)
+            .map(|&v| Invalid::ScheduleValue(v))
+            .collect();
+        if !invalid.is_empty() {
+            return Err(Error::Config(invalid));
+        }
+        Ok(Schedule {
+            driver,
+            curve: Curve::Exponential(sorted(vec![start, end])),
+        })
+    }
+
+    // Piecewise linear through (driver value, parameter value) pairs
+    pub fn keyframes(driver: Driver, keys: Vec<(f64, f64)>) -> Schedule {
+        Schedule::try_keyframes(driver, keys).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like keyframes, but returns an Error instead of panicking when there
+    // are none
+    pub fn try_keyframes(driver: Driver, keys: Vec<(f64, f64)>) -> Result<Schedule> {
+        if keys.is_empty() {
+            return Err(Error::Config(vec![Invalid::Keyframes]));
+        }
+        Ok(Schedule {
+            driver,
+            curve: Curve::Linear(sorted(keys)),
+        })
+    }
+
+    pub fn function<F: Fn(f64) -> f64 + Send + Sync + 'static>(driver: Driver, f: F) -> Schedule {
+        Schedule {
+            driver,
+            curve: Curve::Function(Box::new(f)),
+        }
+    }
+
+    pub fn driver(&self) -> Driver {
+        self.driver
+    }
+
+    pub fn value(&self, count: usize, radius: f64) -> f64 {
+        let x = match self.driver {
+            Driver::Count => count as f64,
+            Driver::Radius => radius,
+        };
+        match &self.curve {
+            Curve::Linear(keys) => interpolate(keys, x, |a, b, t| a + (b - a) * t),
+            Curve::Exponential(keys) => interpolate(keys, x, |a, b, t| a * (b / a).powf(t)),
+            Curve::Function(f) => f(x),
+        }
+    }
+}
+
+fn sorted(mut keys: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+    keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+    keys
+}
+
+fn interpolate(keys: &[(f64, f64)], x: f64, between: impl Fn(f64, f64, f64) -> f64) -> f64 {
+    let next = keys.partition_point(|&(k, _)| k <= x);
+    if next == 0 {
+        return keys[0].1;
+    }
+    if next == keys.len() {
+        return keys[keys.len() - 1].1;
+    }
+    let (x0, y0) = keys[next - 1];
+    let (x1, y1) = keys[next];
+    between(y0, y1, (x - x0) / (x1 - x0))
+}
@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// A ParticleSink receives every particle as soon as it joins an
+// aggregation, see set_sink on the aggregations.
+//
+// Each record is written out in full and flushed before write returns, so
+// a file that a run leaves behind when it dies partway only ever misses
+// the particles that hadn't joined yet, and other programs can follow it
+// while it grows.
+//
+// Flat aggregations write 0 for z.
+//
+// Sinks are Send, so an aggregation holding one can still be moved to
+// another thread.
+pub trait ParticleSink: Send {
+    fn write(&mut self, index: usize, parent: usize, p: [f64; 3]) -> io::Result<()>;
+}
+
+// Same layout as save_csv, a header line and then one particle per line
+pub struct CsvSink<W: Write> {
+    out: W,
+}
+
+impl CsvSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        CsvSink::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(b"index,parent,x,y,z\n")?;
+        out.flush()?;
+        Ok(CsvSink { out })
+    }
+}
+
+impl<W: Write + Send> ParticleSink for CsvSink<W> {
+    fn write(&mut self, index: usize, parent: usize, [x, y, z]: [f64; 3]) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{},{},{:.04},{:.04},{:.04}",
+            index, parent, x, y, z
+        )?;
+        self.out.flush()
+    }
+}
+
+// Fixed size 40 byte records without a header: index and parent as little
+// endian u64, then x, y and z as little endian f64. A truncated file is
+// valid up to its last whole record.
+pub const BINARY_RECORD_SIZE: usize = 40;
+
+pub struct BinarySink<W: Write> {
+    out: W,
+}
+
+impl BinarySink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(BinarySink::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> BinarySink<W> {
+    pub fn new(out: W) -> Self {
+        BinarySink { out }
+    }
+}
+
+impl<W: Write + Send> ParticleSink for BinarySink<W> {
+    fn write(&mut self, index: usize, parent: usize, p: [f64; 3]) -> io::Result<()> {
+        let mut record = [0u8; BINARY_RECORD_SIZE];
+        record[0..8].copy_from_slice(&(index as u64).to_le_bytes());
+        record[8..16].copy_from_slice(&(parent as u64).to_le_bytes());
+        for (chunk, c) in record[16..].chunks_exact_mut(8).zip(p.iter()) {
+            chunk.copy_from_slice(&c.to_le_bytes());
+        }
+        self.out.write_all(&record)?;
+        self.out.flush()
+    }
+}
+
+// One JSON object per line, {"index":0,"parent":0,"x":0.0,"y":0.0,"z":0.0}
+pub struct JsonLinesSink<W: Write> {
+    out: W,
+}
+
+impl JsonLinesSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(JsonLinesSink::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(out: W) -> Self {
+        JsonLinesSink { out }
+    }
+}
+
+impl<W: Write + Send> ParticleSink for JsonLinesSink<W> {
+    fn write(&mut self, index: usize, parent: usize, [x, y, z]: [f64; 3]) -> io::Result<()> {
+        // {:?} keeps full precision and always prints a valid JSON number
+        // for finite values
+        writeln!(
+            self.out,
+            "{{\"index\":{},\"parent\":{},\"x\":{:?},\"y\":{:?},\"z\":{:?}}}",
+            index, parent, x, y, z
+        )?;
+        self.out.flush()
+    }
+}
+
+// Hands a particle to the sink, if there is one. The first error detaches
+// the sink and is kept in error, growth carries on without it.
+pub(crate) fn emit(
+    sink: &mut Option<Box<dyn ParticleSink>>,
+    error: &mut Option<io::Error>,
+    index: usize,
+    parent: usize,
+    p: [f64; 3],
+) {
+    if let Some(s) = sink {
+        if let Err(e) = s.write(index, parent, p) {
+            *sink = None;
+            *error = Some(e);
+        }
+    }
+}
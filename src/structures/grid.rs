@@ -0,0 +1,471 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::{BuildHasherDefault, Hasher};
+
+use crate::structures::index::{Nearest, SpatialIndex};
+use crate::structures::{IndexValue2d, IndexValue3d, Vec2d, Vec3d};
+
+const DEFAULT_CELL_SIZE: f64 = 3.;
+
+// Every coarse level is twice as wide as the previous one
+const LEVELS: usize = 14;
+
+// Most occupied cells on the level a search starts from
+const START_CELLS: usize = 8;
+
+// Cells are keyed by a single packed integer, which only needs to be mixed
+// once instead of going through the default SipHash.
+#[derive(Default)]
+pub struct CellHasher {
+    hash: u64,
+}
+
+impl Hasher for CellHasher {
+    fn finish(&self) -> u64 {
+        // splitmix64 finalizer, so that every input bit reaches the low
+        // bits the table is indexed with
+        let mut z = self.hash;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            self.write_u64(u64::from_le_bytes(word));
+        }
+        for &b in chunks.remainder() {
+            self.write_u64(u64::from(b));
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ i).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+}
+
+type Cells<K, V> = HashMap<K, V, BuildHasherDefault<CellHasher>>;
+
+// Calls f for every cell in the box spanned by lo and hi, both inclusive
+fn for_each_cell<const N: usize>(lo: [i64; N], hi: [i64; N], mut f: impl FnMut([i64; N])) {
+    if (0..N).any(|i| lo[i] > hi[i]) {
+        return;
+    }
+    let mut key = lo;
+    loop {
+        f(key);
+        let mut axis = 0;
+        loop {
+            if axis == N {
+                return;
+            }
+            if key[axis] < hi[axis] {
+                key[axis] += 1;
+                break;
+            }
+            key[axis] = lo[axis];
+            axis += 1;
+        }
+    }
+}
+
+pub(crate) fn distance<const N: usize>(a: &[f64; N], b: &[f64; N]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f64>()
+        .sqrt()
+}
+
+enum Item<const N: usize> {
+    Particle(usize),
+    Cell(usize, [i64; N]),
+}
+
+// Entry of the best first search queue, closest first
+struct Candidate<const N: usize> {
+    distance: f64,
+    item: Item<N>,
+}
+
+impl<const N: usize> PartialEq for Candidate<N> {
+    fn eq(&self, other: &Candidate<N>) -> bool {
+        self.distance == other.distance
+    }
+}
+
+impl<const N: usize> Eq for Candidate<N> {}
+
+impl<const N: usize> PartialOrd for Candidate<N> {
+    fn partial_cmp(&self, other: &Candidate<N>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const N: usize> Ord for Candidate<N> {
+    fn cmp(&self, other: &Candidate<N>) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+// Cell of a coarse level and the occupied cells of the level below it
+struct Node<const N: usize> {
+    key: [i64; N],
+    children: Vec<[i64; N]>,
+}
+
+// Hashed uniform grid behind UniformGrid2d, UniformGrid3d and the
+// CompactAggregation index. Cells hold items of type T, which queries turn
+// into a position and a particle id.
+//
+// Particles are bucketed into cells of side cell_size and nearest neighbor
+// queries only visit the cells overlapping the search radius. Walkers that
+// are far from every particle are answered from a stack of coarser
+// levels, searched closest cell first like an octree. Every cell of a
+// coarse level covers 2^N cells of the level below and keeps a list of
+// the ones that are occupied.
+pub(crate) struct Grid<const N: usize, T> {
+    cell_size: f64,
+    cells: Cells<u64, Vec<T>>,
+    levels: Vec<Cells<u64, Node<N>>>,
+    min: [f64; N],
+    max: [f64; N],
+    norm: f64,
+    size: usize,
+}
+
+impl<const N: usize, T> Grid<N, T> {
+    pub(crate) fn new(cell_size: f64) -> Grid<N, T> {
+        Grid {
+            cell_size,
+            cells: Cells::default(),
+            levels: (0..LEVELS).map(|_| Cells::default()).collect(),
+            min: [f64::INFINITY; N],
+            max: [f64::NEG_INFINITY; N],
+            norm: 0.,
+            size: 0,
+        }
+    }
+
+    fn key(p: &[f64; N], cell_size: f64) -> [i64; N] {
+        let mut key = [0; N];
+        for (k, x) in key.iter_mut().zip(p) {
+            *k = (x / cell_size).floor() as i64;
+        }
+        key
+    }
+
+    // Hash map key of a cell, 21 bits per axis are plenty for any aggregate
+    fn pack(key: &[i64; N]) -> u64 {
+        key.iter().enumerate().fold(0, |packed, (i, k)| {
+            packed | (*k as u64 & 0x1f_ffff) << (21 * i)
+        })
+    }
+
+    fn level_size(&self, level: usize) -> f64 {
+        self.cell_size * f64::from(2u32.pow(level as u32 + 1))
+    }
+
+    // Adds `item` located at `p`
+    pub(crate) fn insert(&mut self, p: [f64; N], item: T) {
+        let mut child = Grid::<N, T>::key(&p, self.cell_size);
+        let mut created = !self.cells.contains_key(&Grid::<N, T>::pack(&child));
+        self.cells
+            .entry(Grid::<N, T>::pack(&child))
+            .or_default()
+            .push(item);
+        for level in 0..LEVELS {
+            if !created {
+                break;
+            }
+            let key = child.map(|k| k.div_euclid(2));
+            let cell = Grid::<N, T>::pack(&key);
+            created = !self.levels[level].contains_key(&cell);
+            self.levels[level]
+                .entry(cell)
+                .or_insert_with(|| Node {
+                    key,
+                    children: Vec::new(),
+                })
+                .children
+                .push(child);
+            child = key;
+        }
+        for (i, x) in p.iter().enumerate() {
+            self.min[i] = self.min[i].min(*x);
+            self.max[i] = self.max[i].max(*x);
+        }
+        self.norm = self.norm.max(Grid::<N, T>::length(&p));
+        self.size += 1;
+    }
+
+    fn length(p: &[f64; N]) -> f64 {
+        p.iter().map(|x| x * x).sum::<f64>().sqrt()
+    }
+
+    // Distance from p to the bounding box and to the bounding sphere
+    // around the origin of all particles, whichever is further
+    fn outside_distance(&self, p: &[f64; N]) -> f64 {
+        let mut outside = [0.; N];
+        for (i, d) in outside.iter_mut().enumerate() {
+            *d = (self.min[i] - p[i]).max(p[i] - self.max[i]).max(0.);
+        }
+        Grid::<N, T>::length(&outside).max(Grid::<N, T>::length(p) - self.norm)
+    }
+
+    // Distance from p to the cell `key` on a level with cells of side `size`
+    fn cell_distance(p: &[f64; N], key: &[i64; N], size: f64) -> f64 {
+        (0..N)
+            .map(|i| {
+                let lo = key[i] as f64 * size;
+                (lo - p[i]).max(p[i] - lo - size).max(0.)
+            })
+            .map(|d| d * d)
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    // Finds the particle closest to p, `locate` tells where an item is and
+    // which particle it stands for
+    pub(crate) fn nearest(
+        &self,
+        p: &[f64; N],
+        radius: f64,
+        locate: &impl Fn(&T) -> ([f64; N], usize),
+    ) -> Nearest {
+        if self.size == 0 {
+            return Nearest::Clear(f64::INFINITY);
+        }
+
+        // Walkers close to the aggregate are answered from the fine cells
+        // around them. Every particle within `reach` of p lies in them.
+        let rings = (radius / self.cell_size).ceil().max(1.) as i64;
+        let key = Grid::<N, T>::key(p, self.cell_size);
+        let wall = (0..N)
+            .map(|i| {
+                let lo = key[i] as f64 * self.cell_size;
+                (p[i] - lo).min(lo + self.cell_size - p[i])
+            })
+            .fold(f64::INFINITY, f64::min);
+        let reach = rings as f64 * self.cell_size + wall;
+
+        if self.outside_distance(p) <= reach {
+            let mut best = (0, f64::INFINITY);
+            for_each_cell(key.map(|k| k - rings), key.map(|k| k + rings), |cell| {
+                if let Some(values) = self.cells.get(&Grid::<N, T>::pack(&cell)) {
+                    for value in values {
+                        let (vec, index) = locate(value);
+                        let d = distance(p, &vec);
+                        if d < best.1 {
+                            best = (index, d);
+                        }
+                    }
+                }
+            });
+            if best.1 <= reach {
+                return Nearest::Particle(best.0, best.1);
+            }
+        }
+
+        self.search(p, locate)
+    }
+
+    // Best first search down the levels, the way an octree would be
+    // searched: cells are visited closest first, so the first particle
+    // coming out of the queue is the nearest one.
+    fn search(&self, p: &[f64; N], locate: &impl Fn(&T) -> ([f64; N], usize)) -> Nearest {
+        // start from the finest level that is cheap to scan as a whole
+        let top = (0..LEVELS)
+            .find(|&level| self.levels[level].len() <= START_CELLS)
+            .unwrap_or(LEVELS - 1);
+        let size = self.level_size(top);
+        let mut queue: BinaryHeap<Candidate<N>> = self.levels[top]
+            .values()
+            .map(|node| Candidate {
+                distance: Grid::<N, T>::cell_distance(p, &node.key, size),
+                item: Item::Cell(top + 1, node.key),
+            })
+            .collect();
+
+        while let Some(Candidate { distance: d, item }) = queue.pop() {
+            match item {
+                Item::Particle(index) => return Nearest::Particle(index, d),
+                Item::Cell(0, key) => {
+                    // only the closest particle of a cell can be the nearest
+                    let mut best = (0, f64::INFINITY);
+                    for value in &self.cells[&Grid::<N, T>::pack(&key)] {
+                        let (vec, index) = locate(value);
+                        let d = distance(p, &vec);
+                        if d < best.1 {
+                            best = (index, d);
+                        }
+                    }
+                    queue.push(Candidate {
+                        distance: best.1,
+                        item: Item::Particle(best.0),
+                    });
+                }
+                Item::Cell(level, key) => {
+                    let size = if level == 1 {
+                        self.cell_size
+                    } else {
+                        self.level_size(level - 2)
+                    };
+                    for child in &self.levels[level - 1][&Grid::<N, T>::pack(&key)].children {
+                        queue.push(Candidate {
+                            distance: Grid::<N, T>::cell_distance(p, child, size),
+                            item: Item::Cell(level - 1, *child),
+                        });
+                    }
+                }
+            }
+        }
+
+        Nearest::Clear(f64::INFINITY)
+    }
+
+    // Lists the particles within radius of p
+    pub(crate) fn within(
+        &self,
+        p: &[f64; N],
+        radius: f64,
+        locate: &impl Fn(&T) -> ([f64; N], usize),
+    ) -> Vec<usize> {
+        let mut found = Vec::new();
+        let lo = Grid::<N, T>::key(&p.map(|x| x - radius), self.cell_size);
+        let hi = Grid::<N, T>::key(&p.map(|x| x + radius), self.cell_size);
+        for_each_cell(lo, hi, |cell| {
+            if let Some(values) = self.cells.get(&Grid::<N, T>::pack(&cell)) {
+                for value in values {
+                    let (vec, index) = locate(value);
+                    if distance(p, &vec) <= radius {
+                        found.push(index);
+                    }
+                }
+            }
+        });
+        found
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        self.cells.values().flatten()
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(crate) fn cell_size(&self) -> f64 {
+        self.cell_size
+    }
+}
+
+// Hashed uniform grid for 2d aggregations. Walkers within a few cells of
+// the aggregate are answered without a tree descent, which suits evenly
+// sized particles. `cargo bench --bench spatial_index` compares it with
+// the RTree.
+pub struct UniformGrid2d {
+    grid: Grid<2, ([f64; 2], usize)>,
+}
+
+impl Default for UniformGrid2d {
+    fn default() -> UniformGrid2d {
+        UniformGrid2d::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl UniformGrid2d {
+    pub fn new(cell_size: f64) -> UniformGrid2d {
+        UniformGrid2d {
+            grid: Grid::new(cell_size),
+        }
+    }
+
+    pub fn cell_size(&self) -> f64 {
+        self.grid.cell_size()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = IndexValue2d> + '_ {
+        self.grid.iter().map(|&(vec, index)| IndexValue2d {
+            vec: vec.into(),
+            index,
+        })
+    }
+}
+
+impl SpatialIndex<Vec2d> for UniformGrid2d {
+    fn insert(&mut self, vec: Vec2d, id: usize) {
+        self.grid.insert(vec.as_slice(), (vec.as_slice(), id));
+    }
+
+    fn nearest(&self, p: &Vec2d, radius: f64) -> Nearest {
+        self.grid.nearest(&p.as_slice(), radius, &|entry| *entry)
+    }
+
+    fn within(&self, p: &Vec2d, radius: f64) -> Vec<usize> {
+        self.grid.within(&p.as_slice(), radius, &|entry| *entry)
+    }
+
+    fn size(&self) -> usize {
+        self.grid.size()
+    }
+
+    fn clear(&mut self) {
+        self.grid = Grid::new(self.grid.cell_size());
+    }
+}
+
+// Hashed uniform grid for 3d aggregations, see UniformGrid2d.
+pub struct UniformGrid3d {
+    grid: Grid<3, ([f64; 3], usize)>,
+}
+
+impl Default for UniformGrid3d {
+    fn default() -> UniformGrid3d {
+        UniformGrid3d::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl UniformGrid3d {
+    pub fn new(cell_size: f64) -> UniformGrid3d {
+        UniformGrid3d {
+            grid: Grid::new(cell_size),
+        }
+    }
+
+    pub fn cell_size(&self) -> f64 {
+        self.grid.cell_size()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = IndexValue3d> + '_ {
+        self.grid.iter().map(|&(vec, index)| IndexValue3d {
+            vec: vec.into(),
+            index,
+        })
+    }
+}
+
+impl SpatialIndex<Vec3d> for UniformGrid3d {
+    fn insert(&mut self, vec: Vec3d, id: usize) {
+        self.grid.insert(vec.as_slice(), (vec.as_slice(), id));
+    }
+
+    fn nearest(&self, p: &Vec3d, radius: f64) -> Nearest {
+        self.grid.nearest(&p.as_slice(), radius, &|entry| *entry)
+    }
+
+    fn within(&self, p: &Vec3d, radius: f64) -> Vec<usize> {
+        self.grid.within(&p.as_slice(), radius, &|entry| *entry)
+    }
+
+    fn size(&self) -> usize {
+        self.grid.size()
+    }
+
+    fn clear(&mut self) {
+        self.grid = Grid::new(self.grid.cell_size());
+    }
+}
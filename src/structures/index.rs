@@ -0,0 +1,91 @@
+use rstar::RTree;
+
+use crate::structures::{IndexValue2d, IndexValue3d, Vec2d, Vec3d};
+
+// Result of a nearest neighbor query.
+//
+// Particle holds the index of the closest particle and the distance to it.
+//
+// Clear means that no particle lies closer than the given distance. Indices
+// that only search the neighborhood of the query point answer with it when
+// that neighborhood is empty.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Nearest {
+    Particle(usize, f64),
+    Clear(f64),
+}
+
+/// Spatial index used by the aggregations to find the particle closest to a walker.
+pub trait SpatialIndex<V> {
+    /// Adds the particle `id` located at `vec`.
+    fn insert(&mut self, vec: V, id: usize);
+
+    /// Looks up the particle closest to `p`.
+    ///
+    /// Every particle within `radius` of `p` has to be considered, anything
+    /// further away may be skipped and reported as `Nearest::Clear`.
+    fn nearest(&self, p: &V, radius: f64) -> Nearest;
+
+    /// Lists every particle within `radius` of `p`, in no particular order.
+    fn within(&self, p: &V, radius: f64) -> Vec<usize>;
+
+    /// Number of particles in the index.
+    fn size(&self) -> usize;
+
+    /// Removes every particle.
+    fn clear(&mut self);
+}
+
+impl SpatialIndex<Vec2d> for RTree<IndexValue2d> {
+    fn insert(&mut self, vec: Vec2d, id: usize) {
+        RTree::insert(self, IndexValue2d { vec, index: id });
+    }
+
+    fn nearest(&self, p: &Vec2d, _radius: f64) -> Nearest {
+        match self.nearest_neighbor(&IndexValue2d { vec: *p, index: 0 }) {
+            Some(nearest) => Nearest::Particle(nearest.index, p.distance(nearest.vec)),
+            None => Nearest::Clear(f64::INFINITY),
+        }
+    }
+
+    fn within(&self, p: &Vec2d, radius: f64) -> Vec<usize> {
+        self.locate_within_distance(IndexValue2d { vec: *p, index: 0 }, radius * radius)
+            .map(|value| value.index)
+            .collect()
+    }
+
+    fn size(&self) -> usize {
+        RTree::size(self)
+    }
+
+    fn clear(&mut self) {
+        *self = RTree::new();
+    }
+}
+
+impl SpatialIndex<Vec3d> for RTree<IndexValue3d> {
+    fn insert(&mut self, vec: Vec3d, id: usize) {
+        RTree::insert(self, IndexValue3d { vec, index: id });
+    }
+
+    fn nearest(&self, p: &Vec3d, _radius: f64) -> Nearest {
+        match self.nearest_neighbor(&IndexValue3d { vec: *p, index: 0 }) {
+            Some(nearest) => Nearest::Particle(nearest.index, p.distance(nearest.vec)),
+            None => Nearest::Clear(f64::INFINITY),
+        }
+    }
+
+    fn within(&self, p: &Vec3d, radius: f64) -> Vec<usize> {
+        self.locate_within_distance(IndexValue3d { vec: *p, index: 0 }, radius * radius)
+            .map(|value| value.index)
+            .collect()
+    }
+
+    fn size(&self) -> usize {
+        RTree::size(self)
+    }
+
+    fn clear(&mut self) {
+        *self = RTree::new();
+    }
+}
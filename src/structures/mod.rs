@@ -1,7 +1,11 @@
 // extern crate rand;
+pub mod grid;
+pub mod index;
 pub mod vec2d;
 pub mod vec3d;
 
+pub use grid::{UniformGrid2d, UniformGrid3d};
+pub use index::{Nearest, SpatialIndex};
 pub use vec2d::Vec2d;
 pub use vec3d::Vec3d;
 
@@ -0,0 +1,462 @@
+use rand::Rng;
+use rstar::RTree;
+
+use std::f64::consts::PI;
+
+use crate::aggregation::Aggregation;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::structures::{vec3d, IndexValue3d, Nearest, SpatialIndex, Vec3d};
+use crate::{
+    DEFAULT_ATTRACTION_DISTANCE, DEFAULT_MIN_MOVE_DISTANCE, DEFAULT_PARTICLE_SPACING,
+    DEFAULT_STICKINESS, DEFAULT_STUBBORNNESS,
+};
+
+// Launch points closer to the aggregate than attraction_distance are
+// drawn again, this many times at most
+const MAX_LAUNCH_ATTEMPTS: usize = 100_000;
+
+// Surface is what a SurfaceAggregation grows on. All of them are bounded,
+// so walkers are launched anywhere on the surface rather than from a
+// launch sphere.
+//
+// Sphere is centered on the origin.
+//
+// Cylinder runs along the z axis from -height / 2 to height / 2, walkers
+// bounce off its ends.
+//
+// Torus lies in the xy plane, major is the distance from the origin to
+// the center of the tube and minor the radius of the tube.
+//
+// Heightfield is z = height(x, y) over the rectangle from min to max,
+// walkers bounce off its edges. Walkers start evenly spread over the
+// rectangle rather than over the surface itself. Slopes much steeper than 1
+// on the scale of attraction_distance crowd particles together. Height has
+// to be Send and Sync, so aggregations on it can still move between
+// threads.
+pub enum Surface {
+    Sphere {
+        radius: f64,
+    },
+    Cylinder {
+        radius: f64,
+        height: f64,
+    },
+    Torus {
+        major: f64,
+        minor: f64,
+    },
+    Heightfield {
+        height: Box<dyn Fn(f64, f64) -> f64 + Send + Sync>,
+        min: [f64; 2],
+        max: [f64; 2],
+    },
+}
+
+impl Surface {
+    pub fn heightfield<F: Fn(f64, f64) -> f64 + Send + Sync + 'static>(
+        height: F,
+        min: [f64; 2],
+        max: [f64; 2],
+    ) -> Surface {
+        Surface::Heightfield {
+            height: Box::new(height),
+            min,
+            max,
+        }
+    }
+
+    /// The point of the surface closest to `p`, or straight above or below
+    /// it for heightfields.
+    pub fn project(&self, p: Vec3d) -> Vec3d {
+        match self {
+            Surface::Sphere { radius } => p.normalized() * *radius,
+            Surface::Cylinder { radius, height } => {
+                let around = Vec3d::new(p.x, p.y, 0.).normalized() * *radius;
+                Vec3d::new(around.x, around.y, reflect(p.z, -height / 2., height / 2.))
+            }
+            Surface::Torus { major, minor } => {
+                let center = Vec3d::new(p.x, p.y, 0.).normalized() * *major;
+                center + (p - center).normalized() * *minor
+            }
+            Surface::Heightfield { height, min, max } => {
+                let x = reflect(p.x, min[0], max[0]);
+                let y = reflect(p.y, min[1], max[1]);
+                Vec3d::new(x, y, height(x, y))
+            }
+        }
+    }
+
+    /// Unit normal of the surface at `p`, which has to lie on it.
+    pub fn normal(&self, p: Vec3d) -> Vec3d {
+        match self {
+            Surface::Sphere { .. } => p.normalized(),
+            Surface::Cylinder { .. } => Vec3d::new(p.x, p.y, 0.).normalized(),
+            Surface::Torus { major, .. } => {
+                (p - Vec3d::new(p.x, p.y, 0.).normalized() * *major).normalized()
+            }
+            Surface::Heightfield { height, .. } => {
+                let h = 1e-4;
+                let dx = (height(p.x + h, p.y) - height(p.x - h, p.y)) / (2. * h);
+                let dy = (height(p.x, p.y + h) - height(p.x, p.y - h)) / (2. * h);
+                Vec3d::new(-dx, -dy, 1.).normalized()
+            }
+        }
+    }
+
+    // A random point on the surface
+    fn random_point(&self) -> Vec3d {
+        let mut rng = rand::thread_rng();
+        match self {
+            Surface::Sphere { radius } => Vec3d::random().normalized() * *radius,
+            Surface::Cylinder { radius, height } => {
+                let a: f64 = rng.gen_range(0., 2. * PI);
+                let z = rng.gen_range(-height / 2., height / 2.);
+                Vec3d::new(a.cos() * radius, a.sin() * radius, z)
+            }
+            Surface::Torus { major, minor } => loop {
+                // the outside of the tube has more area than the inside
+                let u: f64 = rng.gen_range(0., 2. * PI);
+                let v: f64 = rng.gen_range(0., 2. * PI);
+                let w = major + minor * v.cos();
+                if rng.gen_range(0., major + minor) <= w {
+                    return Vec3d::new(w * u.cos(), w * u.sin(), minor * v.sin());
+                }
+            },
+            Surface::Heightfield { height, min, max } => {
+                let x = rng.gen_range(min[0], max[0]);
+                let y = rng.gen_range(min[1], max[1]);
+                Vec3d::new(x, y, height(x, y))
+            }
+        }
+    }
+
+    // Smallest radius of the surface, of the tube and of the hole for a
+    // torus. Particles on opposite sides of it are at least twice as far
+    // apart in a straight line. Heightfields have no such radius to check.
+    fn narrowest(&self) -> f64 {
+        match self {
+            Surface::Sphere { radius } => *radius,
+            Surface::Cylinder { radius, .. } => *radius,
+            Surface::Torus { major, minor } => minor.min(major - minor),
+            Surface::Heightfield { .. } => f64::INFINITY,
+        }
+    }
+
+    // Longest step a walker may take at once without cutting across the
+    // surface. Heightfields have no radius to go by, so their walkers keep
+    // to attraction_distance and follow the surface closely.
+    fn max_step(&self, attraction_distance: f64) -> f64 {
+        match self {
+            Surface::Sphere { radius } => radius / 2.,
+            Surface::Cylinder { radius, .. } => radius / 2.,
+            Surface::Torus { minor, .. } => minor / 2.,
+            Surface::Heightfield { .. } => attraction_distance,
+        }
+    }
+
+    // Moves p in a random direction along the surface, by at most distance
+    // in a straight line. Projecting onto a steep heightfield can lengthen
+    // a step, so those are shortened until they fit, or p stays where it is
+    // when the surface is too steep in that direction.
+    fn walk(&self, p: Vec3d, distance: f64) -> Vec3d {
+        let n = self.normal(p);
+        let mut direction = Vec3d::random();
+        direction = (direction - n * direction.dot(n)).normalized();
+        let mut step = distance;
+        while step >= distance * 1e-3 {
+            let q = self.project(p + direction * step);
+            if q.distance(p) <= distance {
+                return q;
+            }
+            step /= 2.;
+        }
+        p
+    }
+}
+
+// Folds x back into [lo, hi] as if it bounced off the ends
+fn reflect(x: f64, lo: f64, hi: f64) -> f64 {
+    let span = hi - lo;
+    if span <= 0. {
+        return lo;
+    }
+    let t = (x - lo).rem_euclid(2. * span);
+    lo + if t > span { 2. * span - t } else { t }
+}
+
+// SurfaceAggregation grows on a Surface instead of in free space. Walkers
+// and particles stay on the surface, and distances are measured straight
+// through space, which is close to the distance along the surface at the
+// scale particles interact on. The particles make a point set like a
+// ConvexAggregation's, so an RTree index can be rendered with
+// Raycaster::convex.
+//
+// That only holds while the surface is wider than attraction_distance
+// everywhere, otherwise walkers would stick across a thin tube or hole to
+// particles that are far away along the surface. Growth checks it and
+// returns Error::SurfaceTooNarrow when it doesn't hold.
+//
+// The growth parameters mean the same as in ConvexAggregation.
+pub struct SurfaceAggregation<I = RTree<IndexValue3d>> {
+    particle_spacing: f64,
+    attraction_distance: f64,
+    min_move_distanse: f64,
+    stubbornness: usize,
+    stickiness: f64,
+    surface: Surface,
+    join_attempts: Vec<usize>,
+    pub points: Vec<Vec3d>,
+    pub parents: Vec<usize>,
+    pub index: I,
+}
+
+impl SurfaceAggregation {
+    pub fn new(surface: Surface) -> SurfaceAggregation {
+        SurfaceAggregation::with_index(surface, RTree::new())
+    }
+}
+
+impl<I: SpatialIndex<Vec3d>> SurfaceAggregation<I> {
+    pub fn with_index(surface: Surface, index: I) -> SurfaceAggregation<I> {
+        SurfaceAggregation {
+            particle_spacing: DEFAULT_PARTICLE_SPACING,
+            attraction_distance: DEFAULT_ATTRACTION_DISTANCE,
+            min_move_distanse: DEFAULT_MIN_MOVE_DISTANCE,
+            stubbornness: DEFAULT_STUBBORNNESS,
+            stickiness: DEFAULT_STICKINESS,
+            surface,
+            join_attempts: Vec::new(),
+            points: Vec::new(),
+            parents: Vec::new(),
+            index,
+        }
+    }
+
+    pub fn set_particle_spacing(&mut self, a: f64) {
+        self.particle_spacing = a;
+    }
+
+    pub fn set_attraction_distance(&mut self, a: f64) {
+        self.attraction_distance = a;
+    }
+
+    pub fn set_min_move_distance(&mut self, a: f64) {
+        self.min_move_distanse = a;
+    }
+
+    pub fn set_stubbornness(&mut self, a: usize) {
+        self.stubbornness = a;
+    }
+
+    pub fn set_stickness(&mut self, a: f64) {
+        self.stickiness = a;
+    }
+
+    // Sets every growth parameter at once from a checked Config
+    pub fn set_config(&mut self, a: Config) {
+        self.set_particle_spacing(a.particle_spacing());
+        self.set_attraction_distance(a.attraction_distance());
+        self.set_min_move_distance(a.min_move_distance());
+        self.set_stubbornness(a.stubbornness());
+        self.set_stickness(a.stickiness());
+    }
+
+    pub fn surface(&self) -> &Surface {
+        &self.surface
+    }
+
+    // A random point on the surface, away from the aggregate. Panics once
+    // the aggregate covers about all of the surface.
+    pub fn random_starting_position(&self) -> Vec3d {
+        self.try_random_starting_position()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like random_starting_position, but returns Error::SurfaceFull instead
+    // of panicking
+    pub fn try_random_starting_position(&self) -> Result<Vec3d> {
+        self.launch_position().ok_or(Error::SurfaceFull)
+    }
+
+    fn launch_position(&self) -> Option<Vec3d> {
+        for _ in 0..MAX_LAUNCH_ATTEMPTS {
+            let p = self.surface.random_point();
+            match self.index.nearest(&p, self.search_radius()) {
+                Nearest::Particle(_, d) if d < self.attraction_distance => continue,
+                _ => return Some(p),
+            }
+        }
+        None
+    }
+
+    fn should_join(&mut self, parent: usize) -> bool {
+        self.join_attempts[parent] += 1;
+        if self.join_attempts[parent] < self.stubbornness {
+            return false;
+        }
+        rand::thread_rng().gen_range(0., 1.) <= self.stickiness
+    }
+
+    // Projecting onto a heightfield moves p up or down rather than towards
+    // the parent, so it is drawn in again until it sits at particle_spacing
+    fn place_particle(&self, p: Vec3d, parent: usize) -> Vec3d {
+        let parent = self.points[parent];
+        let mut p = self
+            .surface
+            .project(vec3d::lerp(parent, p, self.particle_spacing));
+        for _ in 0..20 {
+            if (p.distance(parent) - self.particle_spacing).abs() < self.particle_spacing * 1e-6 {
+                break;
+            }
+            p = self
+                .surface
+                .project(vec3d::lerp(parent, p, self.particle_spacing));
+        }
+        p
+    }
+
+    // Adds p as a seed of its own. Growing from several seeds needs them
+    // added this way, add(p, 0) joins p to the first particle once there is
+    // one.
+    pub fn add_seed(&mut self, p: Vec3d) {
+        self.add(p, usize::MAX);
+    }
+
+    // Adds p, moved onto the surface. A parent that isn't an already added
+    // particle makes p a seed.
+    pub fn add(&mut self, p: Vec3d, parent: usize) {
+        let id = self.points.len();
+        let parent = if parent < id { parent } else { id };
+        let p = self.surface.project(p);
+        self.index.insert(p, id);
+        self.points.push(p);
+        self.parents.push(parent);
+        self.join_attempts.push(0);
+    }
+
+    // See ConvexAggregation::search_radius
+    fn search_radius(&self) -> f64 {
+        self.attraction_distance * 2.
+    }
+
+    pub fn add_particle(&mut self) {
+        if let Err(e) = self.try_add_particle() {
+            panic!("{}", e);
+        }
+    }
+
+    // Like add_particle, but returns an Error instead of panicking when
+    // there is nothing to grow from, no room left to start walkers or the
+    // surface is too narrow
+    pub fn try_add_particle(&mut self) -> Result<()> {
+        if self.points.is_empty() {
+            return Err(Error::Empty);
+        }
+        let narrowest = self.surface.narrowest();
+        if narrowest < self.attraction_distance {
+            return Err(Error::SurfaceTooNarrow(narrowest));
+        }
+
+        let mut p = self.try_random_starting_position()?;
+
+        loop {
+            let d = match self.index.nearest(&p, self.search_radius()) {
+                Nearest::Particle(parent, d) if d < self.attraction_distance => {
+                    if !self.should_join(parent) {
+                        // push particle away a bit
+                        p = self.surface.project(vec3d::lerp(
+                            self.points[parent],
+                            p,
+                            self.attraction_distance + self.min_move_distanse,
+                        ));
+                        continue;
+                    }
+
+                    // adjust particle position in relation to its parent
+                    p = self.place_particle(p, parent);
+
+                    // add the point
+                    self.add(p, parent);
+                    return Ok(());
+                }
+                Nearest::Particle(_, d) | Nearest::Clear(d) => d,
+            };
+
+            // move randomly along the surface
+            let m = self
+                .min_move_distanse
+                .max(d - self.attraction_distance)
+                .min(self.surface.max_step(self.attraction_distance));
+            p = self.surface.walk(p, m);
+        }
+    }
+
+    pub fn save_csv(&self, name: &str) -> std::io::Result<()> {
+        println!("Saving to csv");
+        use std::fs::File;
+        use std::io::prelude::*;
+
+        let mut file = File::create(name)?;
+        file.write_all(b"index,parent,x,y,z\n")?;
+
+        for (index, (val, parent)) in self.points.iter().zip(&self.parents).enumerate() {
+            writeln!(
+                file,
+                "{},{},{:.04},{:.04},{:.04}",
+                index, parent, val.x, val.y, val.z
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<I: SpatialIndex<Vec3d>> Aggregation for SurfaceAggregation<I> {
+    type Vector = Vec3d;
+
+    fn add(&mut self, p: Vec3d, parent: usize) {
+        self.add(p, parent)
+    }
+
+    fn add_particle(&mut self) {
+        self.add_particle()
+    }
+
+    fn try_add_particle(&mut self) -> Result<()> {
+        self.try_add_particle()
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn dimensions(&self) -> usize {
+        3
+    }
+
+    fn position(&self, index: usize) -> Vec3d {
+        self.points[index]
+    }
+
+    fn parent(&self, index: usize) -> usize {
+        self.parents[index]
+    }
+
+    fn within(&self, p: Vec3d, radius: f64) -> Vec<usize> {
+        self.index.within(&p, radius)
+    }
+
+    fn particle_spacing(&self) -> f64 {
+        self.particle_spacing
+    }
+
+    fn attraction_distance(&self) -> f64 {
+        self.attraction_distance
+    }
+
+    fn bounding_radius(&self) -> f64 {
+        // nothing keeps track of it, walkers don't need a launch sphere
+        self.points.iter().map(|p| p.length()).fold(0., f64::max)
+    }
+}
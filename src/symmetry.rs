@@ -0,0 +1,198 @@
+use std::f64::consts::PI;
+
+use crate::structures::{Vec2d, Vec3d};
+
+pub type Matrix2 = [[f64; 2]; 2];
+pub type Matrix3 = [[f64; 3]; 3];
+
+// Symmetry2d is the group every particle that joins a FlatAggregation is
+// copied under, about the origin.
+//
+// Rotation(n) copies it n - 1 times, turned by multiples of 360 / n degrees.
+//
+// Mirror copies it across the y axis.
+//
+// Dihedral(n) is Rotation(n) together with n mirror lines, the symmetry of
+// a snowflake for n = 6.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum Symmetry2d {
+    #[default]
+    None,
+    Rotation(usize),
+    Mirror,
+    Dihedral(usize),
+}
+
+impl Symmetry2d {
+    // Every operation of the group but the identity
+    pub fn operations(&self) -> Vec<Matrix2> {
+        let rotation = |k: usize, n: usize| {
+            let (sin, cos) = (2. * PI * k as f64 / n as f64).sin_cos();
+            [[cos, -sin], [sin, cos]]
+        };
+        let mirror = [[-1., 0.], [0., 1.]];
+
+        match *self {
+            Symmetry2d::None => Vec::new(),
+            Symmetry2d::Rotation(n) => (1..n).map(|k| rotation(k, n)).collect(),
+            Symmetry2d::Mirror => vec![mirror],
+            Symmetry2d::Dihedral(n) => (1..n)
+                .map(|k| rotation(k, n))
+                .chain((0..n).map(|k| mul2(rotation(k, n), mirror)))
+                .collect(),
+        }
+    }
+}
+
+// Symmetry3d is the group every particle that joins a ConvexAggregation is
+// copied under, about the origin.
+//
+// Rotation(n) turns around the z axis by multiples of 360 / n degrees.
+//
+// Mirror copies across the yz plane.
+//
+// Dihedral(n) is Rotation(n) together with n half turns around axes in the
+// xy plane.
+//
+// Tetrahedral, Octahedral and Icosahedral are the rotations of the regular
+// tetrahedron, cube and icosahedron, 12, 24 and 60 of them including the
+// identity. The cube and the tetrahedron are axis aligned, the icosahedron
+// has a vertex at (0, 1, golden ratio).
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum Symmetry3d {
+    #[default]
+    None,
+    Rotation(usize),
+    Mirror,
+    Dihedral(usize),
+    Tetrahedral,
+    Octahedral,
+    Icosahedral,
+}
+
+impl Symmetry3d {
+    // Every operation of the group but the identity
+    pub fn operations(&self) -> Vec<Matrix3> {
+        let rotation =
+            |k: usize, n: usize| axis_rotation([0., 0., 1.], 2. * PI * k as f64 / n as f64);
+        let half_turn = [[1., 0., 0.], [0., -1., 0.], [0., 0., -1.]];
+        // a third of a turn around (1, 1, 1), cycling the axes
+        let cycle = [[0., 0., 1.], [1., 0., 0.], [0., 1., 0.]];
+
+        let group = match *self {
+            Symmetry3d::None => Vec::new(),
+            Symmetry3d::Rotation(n) => (0..n).map(|k| rotation(k, n)).collect(),
+            Symmetry3d::Mirror => vec![IDENTITY, [[-1., 0., 0.], [0., 1., 0.], [0., 0., 1.]]],
+            Symmetry3d::Dihedral(n) => (0..n)
+                .map(|k| rotation(k, n))
+                .chain((0..n).map(|k| mul3(rotation(k, n), half_turn)))
+                .collect(),
+            Symmetry3d::Tetrahedral => generate(&[cycle, axis_rotation([0., 0., 1.], PI)]),
+            Symmetry3d::Octahedral => generate(&[cycle, axis_rotation([0., 0., 1.], PI / 2.)]),
+            Symmetry3d::Icosahedral => {
+                let phi = (1. + 5f64.sqrt()) / 2.;
+                generate(&[cycle, axis_rotation([0., 1., phi], 2. * PI / 5.)])
+            }
+        };
+
+        group.into_iter().filter(|m| !same(*m, IDENTITY)).collect()
+    }
+}
+
+const IDENTITY: Matrix3 = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+
+pub fn apply2(m: Matrix2, p: Vec2d) -> Vec2d {
+    Vec2d::new(m[0][0] * p.x + m[0][1] * p.y, m[1][0] * p.x + m[1][1] * p.y)
+}
+
+pub fn apply3(m: Matrix3, p: Vec3d) -> Vec3d {
+    Vec3d::new(
+        m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z,
+        m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z,
+        m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z,
+    )
+}
+
+fn mul2(a: Matrix2, b: Matrix2) -> Matrix2 {
+    let mut m = [[0.; 2]; 2];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = a[i][0] * b[0][j] + a[i][1] * b[1][j];
+        }
+    }
+    m
+}
+
+fn mul3(a: Matrix3, b: Matrix3) -> Matrix3 {
+    let mut m = [[0.; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = a[i][0] * b[0][j] + a[i][1] * b[1][j] + a[i][2] * b[2][j];
+        }
+    }
+    m
+}
+
+// The operation m carried out in the frame turned by rotation, r m r^T
+pub(crate) fn conjugate2(rotation: Matrix2, m: Matrix2) -> Matrix2 {
+    let [[a, b], [c, d]] = rotation;
+    mul2(mul2(rotation, m), [[a, c], [b, d]])
+}
+
+pub(crate) fn conjugate3(rotation: Matrix3, m: Matrix3) -> Matrix3 {
+    let mut transpose = [[0.; 3]; 3];
+    for (i, row) in rotation.iter().enumerate() {
+        for (j, v) in row.iter().enumerate() {
+            transpose[j][i] = *v;
+        }
+    }
+    mul3(mul3(rotation, m), transpose)
+}
+
+fn same(a: Matrix3, b: Matrix3) -> bool {
+    a.iter()
+        .flatten()
+        .zip(b.iter().flatten())
+        .all(|(x, y)| (x - y).abs() < 1e-9)
+}
+
+// Rodrigues' rotation by angle around axis
+fn axis_rotation(axis: [f64; 3], angle: f64) -> Matrix3 {
+    let a = Vec3d::from(axis).normalized();
+    let (sin, cos) = angle.sin_cos();
+    let t = 1. - cos;
+    [
+        [
+            t * a.x * a.x + cos,
+            t * a.x * a.y - sin * a.z,
+            t * a.x * a.z + sin * a.y,
+        ],
+        [
+            t * a.x * a.y + sin * a.z,
+            t * a.y * a.y + cos,
+            t * a.y * a.z - sin * a.x,
+        ],
+        [
+            t * a.x * a.z - sin * a.y,
+            t * a.y * a.z + sin * a.x,
+            t * a.z * a.z + cos,
+        ],
+    ]
+}
+
+// Closes the generators under multiplication
+fn generate(generators: &[Matrix3]) -> Vec<Matrix3> {
+    let mut group = vec![IDENTITY];
+    let mut next = 0;
+    while next < group.len() {
+        let m = group[next];
+        for &g in generators {
+            let product = mul3(m, g);
+            if !group.iter().any(|&h| same(h, product)) {
+                group.push(product);
+            }
+        }
+        next += 1;
+    }
+    group
+}
@@ -0,0 +1,159 @@
+use crate::structures::grid::{distance, Grid};
+
+// Issue is a broken invariant found by validate on the aggregations.
+//
+// NotFinite is a particle with a NaN or infinite coordinate.
+//
+// Overlap is a pair of particles, and the distance between them, closer
+// together than Tolerances allows.
+//
+// LinkLength is a particle, and the distance to its parent, that is off
+// from particle_spacing by more than Tolerances allows.
+//
+// Detached is a particle whose chain of parents never reaches a seed, it
+// either loops or points past the last particle.
+//
+// Parents is the number of parent entries when it doesn't match the number
+// of points.
+//
+// IndexSize is the number of particles in the spatial index when it doesn't
+// match the number of points, NotIndexed a particle the index doesn't have
+// under its own id where points says it is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Issue {
+    NotFinite(usize),
+    Overlap(usize, usize, f64),
+    LinkLength(usize, f64),
+    Detached(usize),
+    Parents(usize),
+    IndexSize(usize),
+    NotIndexed(usize),
+}
+
+// Tolerances used by validate.
+//
+// Min_distance is the fraction of particle_spacing that particles which
+// aren't linked to each other have to keep between them, half by default.
+// Growth turns down places closer than that to another particle, so grown
+// aggregates, symmetric copies included, pass with the defaults.
+//
+// Link_error is the fraction of particle_spacing a link may be longer or
+// shorter by, 1% by default. Growth places particles exactly, but relax
+// stops once particles move less than its tolerance per pass, 1e-3 by
+// default, and leaves links off by a few times that. Relaxing with a
+// looser tolerance, or with a particle_spacing well below 1, may need a
+// larger link_error, as do aggregates grown with a scheduled
+// particle_spacing.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tolerances {
+    min_distance: f64,
+    link_error: f64,
+}
+
+impl Default for Tolerances {
+    fn default() -> Tolerances {
+        Tolerances {
+            min_distance: 0.5,
+            link_error: 1e-2,
+        }
+    }
+}
+
+impl Tolerances {
+    pub fn new() -> Tolerances {
+        Tolerances::default()
+    }
+
+    pub fn with_min_distance(mut self, a: f64) -> Tolerances {
+        self.min_distance = a;
+        self
+    }
+
+    pub fn with_link_error(mut self, a: f64) -> Tolerances {
+        self.link_error = a;
+        self
+    }
+
+    // Checks the particles and their links, everything but the index
+    pub(crate) fn check<const N: usize>(
+        &self,
+        points: &[[f64; N]],
+        parents: &[usize],
+        spacing: f64,
+    ) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let finite: Vec<bool> = points
+            .iter()
+            .map(|p| p.iter().all(|x| x.is_finite()))
+            .collect();
+        for (i, _) in finite.iter().enumerate().filter(|(_, &f)| !f) {
+            issues.push(Issue::NotFinite(i));
+        }
+
+        if parents.len() != points.len() {
+            issues.push(Issue::Parents(parents.len()));
+        }
+        let parent = |i: usize| parents.get(i).copied().unwrap_or(i);
+
+        for i in 0..points.len() {
+            let p = parent(i);
+            if p != i && p < points.len() && finite[i] && finite[p] {
+                let length = distance(&points[i], &points[p]);
+                if (length - spacing).abs() > self.link_error * spacing {
+                    issues.push(Issue::LinkLength(i, length));
+                }
+            }
+        }
+
+        // walk up from every particle until a seed or a particle already
+        // known to reach one, or not to
+        let mut rooted: Vec<Option<bool>> = vec![None; points.len()];
+        let mut chain = Vec::new();
+        for start in 0..points.len() {
+            let mut i = start;
+            let reached = loop {
+                if let Some(r) = rooted[i] {
+                    break r;
+                }
+                if parent(i) == i {
+                    break true;
+                }
+                if chain.len() > points.len() || parent(i) >= points.len() {
+                    break false;
+                }
+                chain.push(i);
+                i = parent(i);
+            };
+            rooted[i] = Some(reached);
+            for j in chain.drain(..) {
+                rooted[j] = Some(reached);
+            }
+        }
+        for (i, _) in rooted
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| **r == Some(false))
+        {
+            issues.push(Issue::Detached(i));
+        }
+
+        let min_distance = self.min_distance * spacing;
+        if min_distance > 0. {
+            let mut grid = Grid::<N, usize>::new(min_distance);
+            for (i, p) in points.iter().enumerate().filter(|(i, _)| finite[*i]) {
+                grid.insert(*p, i);
+            }
+            for (i, p) in points.iter().enumerate().filter(|(i, _)| finite[*i]) {
+                for j in grid.within(p, min_distance, &|&j| (points[j], j)) {
+                    let linked = parent(i) == j || parent(j) == i;
+                    let d = distance(p, &points[j]);
+                    if j > i && !linked && d < min_distance {
+                        issues.push(Issue::Overlap(i, j, d));
+                    }
+                }
+            }
+        }
+
+        issues
+    }
+}
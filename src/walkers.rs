@@ -0,0 +1,80 @@
+use rand::Rng;
+
+use std::f64::consts::PI;
+
+// Walkers sets up finite density growth, see grow_dense on the
+// aggregations. Instead of releasing walkers one at a time from the launch
+// sphere, the disk or ball of radius around the origin is filled with
+// walkers at the given density, walkers per unit of area or volume, and
+// all of them take a step in turn.
+//
+// Walkers that join, leave the domain or run out of life are replaced
+// with fresh ones at random places in the domain, away from the aggregate,
+// so their number stays the same.
+//
+// Lifetime is the number of steps a walker takes before it is dropped, and
+// evaporation the probability to be dropped at every step. Walkers away
+// from the aggregate take longer steps, as in add_particle, so both are
+// counted in steps rather than distance.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Walkers {
+    radius: f64,
+    density: f64,
+    lifetime: Option<usize>,
+    evaporation: f64,
+}
+
+impl Walkers {
+    pub fn new(radius: f64, density: f64) -> Walkers {
+        Walkers {
+            radius,
+            density,
+            lifetime: None,
+            evaporation: 0.,
+        }
+    }
+
+    pub fn with_lifetime(mut self, a: usize) -> Walkers {
+        self.lifetime = Some(a);
+        self
+    }
+
+    pub fn with_evaporation(mut self, a: f64) -> Walkers {
+        self.evaporation = a;
+        self
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn density(&self) -> f64 {
+        self.density
+    }
+
+    pub fn lifetime(&self) -> Option<usize> {
+        self.lifetime
+    }
+
+    pub fn evaporation(&self) -> f64 {
+        self.evaporation
+    }
+
+    // Number of walkers filling a disk of radius
+    pub(crate) fn count2d(&self) -> usize {
+        (self.density * PI * self.radius.powi(2)).round() as usize
+    }
+
+    // Number of walkers filling a ball of radius
+    pub(crate) fn count3d(&self) -> usize {
+        (self.density * 4. / 3. * PI * self.radius.powi(3)).round() as usize
+    }
+
+    // Whether a walker that has taken age steps is dropped
+    pub(crate) fn expires(&self, age: usize) -> bool {
+        if self.lifetime.is_some_and(|lifetime| age >= lifetime) {
+            return true;
+        }
+        self.evaporation > 0. && rand::thread_rng().gen_range(0., 1.) < self.evaporation
+    }
+}